@@ -0,0 +1,258 @@
+use crate::record::RecordSlice;
+use crate::utility::{drawdown, irr, quantile};
+use crate::TransactionIterator;
+
+#[derive(Debug)]
+pub struct MetricsError(&'static str);
+
+impl std::fmt::Display for MetricsError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        writeln!(f, "MetricsError: {}", self.0)
+    }
+}
+
+impl std::error::Error for MetricsError {}
+
+/// Performance metrics of a backtest.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Metrics {
+    /// Total asset at the end of the iteration.
+    pub final_asset: f64,
+    /// Return of the final asset over the net inflow, or NaN if there
+    /// is no net inflow.
+    pub total_return: f64,
+    /// Internal rate of return of all the inflows, or NaN if it can
+    /// not be solved.
+    pub irr: f64,
+    /// Maximal drawdown of the time-weighted equity curve, which
+    /// ranges from 0 to 1.
+    pub max_drawdown: f64,
+}
+
+/// Metric used to rank backtests.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Metric {
+    FinalAsset,
+    TotalReturn,
+    Irr,
+    MaxDrawdown,
+}
+
+impl Metric {
+    pub const ALL: [Metric; 4] = [
+        Metric::FinalAsset,
+        Metric::TotalReturn,
+        Metric::Irr,
+        Metric::MaxDrawdown,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Metric::FinalAsset => "final_asset",
+            Metric::TotalReturn => "total_return",
+            Metric::Irr => "irr",
+            Metric::MaxDrawdown => "max_drawdown",
+        }
+    }
+
+    /// Value of the metric.
+    pub fn value(&self, metrics: &Metrics) -> f64 {
+        match self {
+            Metric::FinalAsset => metrics.final_asset,
+            Metric::TotalReturn => metrics.total_return,
+            Metric::Irr => metrics.irr,
+            Metric::MaxDrawdown => metrics.max_drawdown,
+        }
+    }
+
+    /// Value of the metric where larger is better, or negative
+    /// infinity if the value is NaN.
+    pub fn score(&self, metrics: &Metrics) -> f64 {
+        let value = match self {
+            Metric::MaxDrawdown => -metrics.max_drawdown,
+            _ => self.value(metrics),
+        };
+        if value.is_nan() {
+            f64::NEG_INFINITY
+        } else {
+            value
+        }
+    }
+}
+
+/// Get the time-weighted equity curve of the iteration.
+///
+/// The curve is the value of one unit invested at the first day, so
+/// that it is not distorted by inflows. Returns `None` if either log
+/// or record is not enabled for the iterator.
+pub fn equity_curve(it: &TransactionIterator) -> Option<Vec<f64>> {
+    let asset = it.asset_log()?;
+    let record = it.record()?;
+    let dates = it.dates();
+    let mut inflows = vec![0.; dates.len()];
+    let mut k = 0;
+    for (i, date) in dates.iter().enumerate() {
+        while k < record.len() && record[k].date() <= *date {
+            if record[k].date() == *date {
+                inflows[i] += record[k].investment();
+            }
+            k += 1;
+        }
+    }
+    let mut equity = Vec::with_capacity(dates.len());
+    let mut value = 1.;
+    for i in 0..dates.len() {
+        if i > 0 && asset[i - 1] != 0. {
+            value *= (asset[i] - inflows[i]) / asset[i - 1];
+        }
+        equity.push(value);
+    }
+    Some(equity)
+}
+
+impl Metrics {
+    /// Evaluate the metrics of an iteration, which requires both log
+    /// and record of the iterator.
+    ///
+    /// The iteration is evaluated at its current date, so it is
+    /// usually called after the strategy finishes.
+    pub fn new(it: &TransactionIterator) -> Result<Self, MetricsError> {
+        let record = it.record().ok_or(MetricsError(
+            "record is not enabled for transaction iterator",
+        ))?;
+        let equity =
+            equity_curve(it).ok_or(MetricsError("log is not enabled for transaction iterator"))?;
+        let final_asset = it.asset();
+        let end_date = it.today();
+        let records = record.records();
+        let days: Vec<f64> = records
+            .iter()
+            .map(|rs| (end_date - rs.date()).num_days() as f64)
+            .collect();
+        let investments: Vec<f64> = records.iter().map(|rs| rs.investment()).collect();
+        let net_inflow: f64 = investments.iter().sum();
+        let total_return = if net_inflow > 0. {
+            final_asset / net_inflow - 1.
+        } else {
+            f64::NAN
+        };
+        let irr = irr(&days, &investments, final_asset, 0.).unwrap_or(f64::NAN);
+        let max_drawdown = drawdown(&equity).into_iter().fold(0., f64::max);
+        Ok(Metrics {
+            final_asset,
+            total_return,
+            irr,
+            max_drawdown,
+        })
+    }
+}
+
+/// Distribution of a metric over several runs.
+#[derive(Debug, Clone)]
+pub struct Distribution {
+    /// Number of runs with a valid value of the metric.
+    pub count: usize,
+    pub mean: f64,
+    pub min: f64,
+    pub max: f64,
+    /// Pairs of `(q, quantile)`.
+    pub quantiles: Vec<(f64, f64)>,
+    /// Index of the worst run by the metric.
+    pub worst: usize,
+    /// Value of the metric of the worst run.
+    pub worst_value: f64,
+}
+
+impl Distribution {
+    /// Summarize `metric` over the runs given by `(index, metrics)`
+    /// pairs, skipping the runs where the metric is NaN. Returns
+    /// `None` if no run is left.
+    ///
+    /// # Arguments
+    ///
+    /// * `runs` - Index and metrics of each run.
+    /// * `metric` - The metric to summarize.
+    /// * `qs` - Quantiles to report, e.g. `[0.05, 0.5, 0.95]`.
+    pub fn new<'a, I>(runs: I, metric: Metric, qs: &[f64]) -> Option<Self>
+    where
+        I: IntoIterator<Item = (usize, &'a Metrics)>,
+    {
+        let runs: Vec<(usize, &Metrics)> = runs
+            .into_iter()
+            .filter(|(_, m)| !metric.value(m).is_nan())
+            .collect();
+        let (worst, worst_metrics) = runs
+            .iter()
+            .min_by(|a, b| metric.score(a.1).total_cmp(&metric.score(b.1)))?;
+        let values: Vec<f64> = runs.iter().map(|(_, m)| metric.value(m)).collect();
+        Some(Distribution {
+            count: values.len(),
+            mean: values.iter().sum::<f64>() / values.len() as f64,
+            min: values.iter().copied().fold(f64::INFINITY, f64::min),
+            max: values.iter().copied().fold(f64::NEG_INFINITY, f64::max),
+            quantiles: qs.iter().map(|&q| (q, quantile(&values, q))).collect(),
+            worst: *worst,
+            worst_value: metric.value(worst_metrics),
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::*;
+
+    #[test]
+    fn test_metrics() {
+        let hs300 = Fund::from(&read_gta("hs300.txt").unwrap());
+        let start_date = NaiveDate::parse_from_str("20150101", "%Y%m%d").unwrap();
+        let end_date = NaiveDate::parse_from_str("20200101", "%Y%m%d").unwrap();
+        let trans = Transaction::new(&[&hs300], Some(start_date), Some(end_date));
+
+        // Buy and hold, whose metrics are given by the NAVs.
+        let mut it = trans.iter(true, true);
+        it.inflow(1000.).unwrap();
+        it.buy(0, 1000., 0.).unwrap();
+        while it.next_day().is_some() {}
+        let metrics = Metrics::new(&it).unwrap();
+        let navs = trans.navs().column(0).to_vec();
+        let growth = navs[navs.len() - 1] / navs[0];
+        assert!((metrics.final_asset - 1000. * growth).abs() < 1e-6);
+        assert!((metrics.total_return - (growth - 1.)).abs() < 1e-9);
+        let years = (it.today() - it.dates()[0]).num_days() as f64 / DAYS_PER_YEAR;
+        assert!((metrics.irr - (growth.powf(1. / years) - 1.)).abs() < 1e-4);
+        let max_drawdown = drawdown(&navs).into_iter().fold(0., f64::max);
+        assert!((metrics.max_drawdown - max_drawdown).abs() < 1e-9);
+        assert!(Metric::MaxDrawdown.score(&metrics) < 0.);
+
+        let it = trans.iter(false, true);
+        assert!(Metrics::new(&it).is_err());
+    }
+
+    #[test]
+    fn test_distribution() {
+        let metrics: Vec<Metrics> = [0.3, f64::NAN, 0.1, 0.2]
+            .iter()
+            .map(|&max_drawdown| Metrics {
+                final_asset: 1.,
+                total_return: 0.,
+                irr: 0.,
+                max_drawdown,
+            })
+            .collect();
+        let runs = metrics.iter().enumerate();
+        let dist = Distribution::new(runs, Metric::MaxDrawdown, &[0.5]).unwrap();
+        assert_eq!(dist.count, 3);
+        assert!((dist.mean - 0.2).abs() < 1e-12);
+        assert_eq!((dist.min, dist.max), (0.1, 0.3));
+        assert_eq!(dist.quantiles, vec![(0.5, 0.2)]);
+        // A larger drawdown is worse.
+        assert_eq!((dist.worst, dist.worst_value), (0, 0.3));
+        assert!(Distribution::new(
+            metrics.iter().enumerate().skip(1).take(1),
+            Metric::MaxDrawdown,
+            &[]
+        )
+        .is_none());
+    }
+}
@@ -0,0 +1,11 @@
+pub mod metrics;
+pub mod sensitivity;
+pub mod stress;
+pub mod sweep;
+pub mod walk_forward;
+
+pub use metrics::{equity_curve, Distribution, Metric, Metrics};
+pub use sensitivity::{start_date_sensitivity, SensitivityTable, StartStep};
+pub use stress::{stress_test, Scenario, StressResult};
+pub use sweep::{sweep, ParamGrid, ParamSet, SweepRow, SweepTable};
+pub use walk_forward::{walk_forward, WalkForward, WalkForwardResult, WindowMode};
@@ -0,0 +1,330 @@
+use std::error::Error;
+use std::io::Write;
+
+use chrono::{Datelike, Duration, NaiveDate};
+
+use crate::analysis::metrics::{Distribution, Metric, Metrics};
+use crate::analysis::sweep::{sweep, ParamGrid, ParamSet};
+use crate::analysis::walk_forward::run_window;
+use crate::export::{csv_field, json_number, json_string, Export};
+use crate::{Transaction, TransactionIterator};
+
+#[derive(Debug)]
+pub struct SensitivityError(&'static str);
+
+impl std::fmt::Display for SensitivityError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        writeln!(f, "SensitivityError: {}", self.0)
+    }
+}
+
+impl std::error::Error for SensitivityError {}
+
+/// Step between the start dates.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StartStep {
+    /// Every trading day.
+    Day,
+    /// The first trading day of every month.
+    Month,
+}
+
+/// Metrics of the runs of every start date and offset.
+#[derive(Debug, Clone)]
+pub struct SensitivityTable {
+    start_dates: Vec<NaiveDate>,
+    offsets: Vec<u32>,
+    /// Metrics of the runs, where the offsets vary the fastest.
+    metrics: Vec<Result<Metrics, String>>,
+}
+
+impl SensitivityTable {
+    pub fn start_dates(&self) -> &[NaiveDate] {
+        &self.start_dates
+    }
+
+    pub fn offsets(&self) -> &[u32] {
+        &self.offsets
+    }
+
+    /// Metrics of the run of the `i`-th start date and the `j`-th
+    /// offset, or the error message if it fails.
+    pub fn get(&self, i: usize, j: usize) -> &Result<Metrics, String> {
+        &self.metrics[i * self.offsets.len() + j]
+    }
+
+    /// Start date and offset of the `k`-th run, where the offsets
+    /// vary the fastest. It locates `Distribution::worst`.
+    pub fn run(&self, k: usize) -> (NaiveDate, u32) {
+        let n = self.offsets.len();
+        (self.start_dates[k / n], self.offsets[k % n])
+    }
+
+    /// Distribution of `metric` over the successful runs, or `None` if
+    /// all the runs fail.
+    ///
+    /// # Arguments
+    ///
+    /// * `metric` - The metric to summarize.
+    /// * `qs` - Quantiles to report, e.g. `[0.05, 0.5, 0.95]`.
+    pub fn distribution(&self, metric: Metric, qs: &[f64]) -> Option<Distribution> {
+        let runs = self.metrics.iter().enumerate();
+        Distribution::new(
+            runs.filter_map(|(k, m)| Some((k, m.as_ref().ok()?))),
+            metric,
+            qs,
+        )
+    }
+
+    /// Write `metric` as a CSV matrix, with one row per start date and
+    /// one column per offset. Failed runs are left empty.
+    pub fn write_heatmap_csv<W: Write>(
+        &self,
+        metric: Metric,
+        writer: &mut W,
+    ) -> Result<(), Box<dyn Error>> {
+        write!(writer, "start_date")?;
+        for offset in &self.offsets {
+            write!(writer, ",{}", offset)?;
+        }
+        writeln!(writer)?;
+        for (i, date) in self.start_dates.iter().enumerate() {
+            write!(writer, "{}", date)?;
+            for j in 0..self.offsets.len() {
+                match self.get(i, j) {
+                    Ok(m) => write!(writer, ",{}", metric.value(m))?,
+                    Err(_) => write!(writer, ",")?,
+                }
+            }
+            writeln!(writer)?;
+        }
+        Ok(())
+    }
+}
+
+impl Export for SensitivityTable {
+    /// Write the table as CSV with one row per run.
+    ///
+    /// Columns are `start_date`, `offset`, the metrics and `error`.
+    fn write_csv<W: Write>(&self, writer: &mut W) -> Result<(), Box<dyn Error>> {
+        let names: Vec<&str> = Metric::ALL.iter().map(|m| m.name()).collect();
+        writeln!(writer, "start_date,offset,{},error", names.join(","))?;
+        for (i, date) in self.start_dates.iter().enumerate() {
+            for (j, offset) in self.offsets.iter().enumerate() {
+                write!(writer, "{},{},", date, offset)?;
+                match self.get(i, j) {
+                    Ok(m) => {
+                        for metric in Metric::ALL {
+                            write!(writer, "{},", metric.value(m))?;
+                        }
+                        writeln!(writer)?;
+                    }
+                    Err(error) => {
+                        writeln!(
+                            writer,
+                            "{}{}",
+                            ",".repeat(Metric::ALL.len()),
+                            csv_field(error)
+                        )?;
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Write the table as a JSON array of objects with the keys
+    /// `start_date`, `offset`, `metrics` and `error`.
+    fn write_json<W: Write>(&self, writer: &mut W) -> Result<(), Box<dyn Error>> {
+        write!(writer, "[")?;
+        for (i, date) in self.start_dates.iter().enumerate() {
+            for (j, offset) in self.offsets.iter().enumerate() {
+                if i != 0 || j != 0 {
+                    write!(writer, ",")?;
+                }
+                write!(
+                    writer,
+                    "{{\"start_date\":\"{}\",\"offset\":{},",
+                    date, offset
+                )?;
+                match self.get(i, j) {
+                    Ok(m) => {
+                        let values: Vec<String> = Metric::ALL
+                            .iter()
+                            .map(|x| format!("\"{}\":{}", x.name(), json_number(x.value(m))))
+                            .collect();
+                        write!(
+                            writer,
+                            "\"metrics\":{{{}}},\"error\":null}}",
+                            values.join(",")
+                        )?;
+                    }
+                    Err(error) => {
+                        write!(
+                            writer,
+                            "\"metrics\":null,\"error\":{}}}",
+                            json_string(error)
+                        )?;
+                    }
+                }
+            }
+        }
+        writeln!(writer, "]")?;
+        Ok(())
+    }
+}
+
+/// Rerun a strategy for every start date and schedule offset.
+///
+/// Results of a strategy depend on when it starts and on which day of
+/// its schedule it trades, e.g. the day of month of `aip_monthly`.
+/// This function runs `f` from each start date in the range with each
+/// offset in parallel by `sweep`, so that the distribution of the
+/// results can be inspected instead of a single lucky or unlucky run.
+///
+/// `f` runs the strategy from the current date of the iterator, which
+/// is the start date, with the given offset, and NAVs before the start
+/// date are kept for strategies with lookbacks. Cash should be
+/// provided by `inflow` in `f`.
+///
+/// # Arguments
+///
+/// * `trans` - The transaction to analyse.
+/// * `first` - The first start date.
+/// * `last` - The last start date.
+/// * `step` - Step between the start dates.
+/// * `offsets` - Offsets passed to `f`, such as days of month or
+///   weekdays.
+/// * `horizon` - Length of each run. If given, runs end at the start
+///   date plus `horizon`, and start dates too late for it are
+///   skipped. Otherwise, all runs end at the end of `trans`.
+/// * `nthreads` - Number of threads for `sweep`.
+/// * `f` - Function running the strategy with an offset.
+#[allow(clippy::too_many_arguments)]
+pub fn start_date_sensitivity<F>(
+    trans: &Transaction,
+    first: NaiveDate,
+    last: NaiveDate,
+    step: StartStep,
+    offsets: &[u32],
+    horizon: Option<Duration>,
+    nthreads: usize,
+    f: F,
+) -> Result<SensitivityTable, Box<dyn Error>>
+where
+    F: Fn(&mut TransactionIterator, u32) -> Result<(), Box<dyn Error>> + Sync,
+{
+    let mut start_dates: Vec<NaiveDate> = Vec::new();
+    for &date in trans.date() {
+        if date < first || date > last {
+            continue;
+        }
+        if horizon.is_some_and(|h| date + h > trans.end_date()) {
+            break;
+        }
+        let new_month = start_dates
+            .last()
+            .is_none_or(|d| (d.year(), d.month()) != (date.year(), date.month()));
+        if step == StartStep::Day || new_month {
+            start_dates.push(date);
+        }
+    }
+    if start_dates.is_empty() || offsets.is_empty() {
+        return Err(Box::new(SensitivityError("no start date or offset to run")));
+    }
+    let grid = ParamGrid::new()
+        .add(
+            "start",
+            &(0..start_dates.len()).map(|i| i as f64).collect::<Vec<_>>(),
+        )
+        .add(
+            "offset",
+            &offsets.iter().map(|&x| x as f64).collect::<Vec<_>>(),
+        );
+    let run = |it: &mut TransactionIterator, params: &ParamSet| f(it, params["offset"] as u32);
+    let table = sweep(trans, &grid, nthreads, |trans, params| {
+        let start_date = start_dates[params["start"] as usize];
+        let metrics = match horizon {
+            Some(h) => {
+                let sliced = trans.slice(None, Some(start_date + h));
+                Metrics::new(&run_window(&sliced, start_date, params, &run)?.0)?
+            }
+            None => Metrics::new(&run_window(trans, start_date, params, &run)?.0)?,
+        };
+        Ok(metrics)
+    })?;
+    Ok(SensitivityTable {
+        start_dates,
+        offsets: offsets.to_vec(),
+        metrics: table.rows().iter().map(|row| row.metrics.clone()).collect(),
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::strategy::aip_monthly;
+    use crate::*;
+
+    #[test]
+    fn test_start_date_sensitivity() {
+        let hs300 = Fund::from(&read_gta("hs300.txt").unwrap());
+        let gz2000 = Fund::from(&read_gta("gz2000.txt").unwrap());
+        let start_date = NaiveDate::parse_from_str("20110101", "%Y%m%d").unwrap();
+        let end_date = NaiveDate::parse_from_str("20240101", "%Y%m%d").unwrap();
+        let trans = Transaction::new(&[&hs300, &gz2000], Some(start_date), Some(end_date));
+        let first = NaiveDate::parse_from_str("20120101", "%Y%m%d").unwrap();
+        let last = NaiveDate::parse_from_str("20201231", "%Y%m%d").unwrap();
+        let horizon = Duration::days(365 * 5);
+        let offsets = [1, 10, 20];
+        let aip =
+            |it: &mut TransactionIterator, day: u32| aip_monthly(it, day, &[1000.; 2], &[2.; 2]);
+        let table = start_date_sensitivity(
+            &trans,
+            first,
+            last,
+            StartStep::Month,
+            &offsets,
+            Some(horizon),
+            0,
+            aip,
+        )
+        .unwrap();
+        // Start dates after January 2019 exceed the end with the horizon.
+        assert_eq!(table.start_dates().len(), 7 * 12 + 1);
+        assert!(table
+            .start_dates()
+            .windows(2)
+            .all(|d| d[0].month() != d[1].month()));
+
+        // Same as running directly.
+        let (i, j) = (30, 1);
+        let sliced = trans.slice(None, Some(table.start_dates()[i] + horizon));
+        let mut it = sliced.iter(true, true);
+        it.goto(table.start_dates()[i]);
+        aip(&mut it, offsets[j]).unwrap();
+        assert_eq!(
+            table.get(i, j).as_ref().unwrap(),
+            &Metrics::new(&it).unwrap()
+        );
+
+        let dist = table.distribution(Metric::Irr, &[0.05, 0.5, 0.95]).unwrap();
+        assert_eq!(dist.count, (7 * 12 + 1) * 3);
+        assert!(dist.min < dist.quantiles[0].1 && dist.quantiles[2].1 < dist.max);
+        assert_eq!(dist.worst_value, dist.min);
+        let (date, offset) = table.run(dist.worst);
+        let i = table.start_dates().iter().position(|&d| d == date).unwrap();
+        let j = offsets.iter().position(|&o| o == offset).unwrap();
+        assert_eq!(table.get(i, j).as_ref().unwrap().irr, dist.min);
+        // Returns swing a lot with the start date.
+        assert!(dist.max - dist.min > 0.1);
+
+        let mut buffer = Vec::new();
+        table.write_heatmap_csv(Metric::Irr, &mut buffer).unwrap();
+        let text = String::from_utf8(buffer).unwrap();
+        let lines: Vec<&str> = text.lines().collect();
+        assert_eq!(lines.len(), 7 * 12 + 2);
+        assert_eq!(lines[0], "start_date,1,10,20");
+        assert!(lines[1].starts_with("2012-01-04,"));
+    }
+}
@@ -0,0 +1,370 @@
+use std::error::Error;
+
+use chrono::{Duration, NaiveDate};
+
+use crate::analysis::metrics::equity_curve;
+use crate::data::DataSlice;
+use crate::record::RecordSlice;
+use crate::{ConciseRecord, Fund, Transaction, TransactionIterator};
+
+#[derive(Debug)]
+pub struct StressError(&'static str);
+
+impl std::fmt::Display for StressError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        writeln!(f, "StressError: {}", self.0)
+    }
+}
+
+impl std::error::Error for StressError {}
+
+/// A historical window whose market moves are replayed.
+///
+/// The window includes `start_date` and excludes `end_date`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Scenario {
+    pub name: String,
+    pub start_date: NaiveDate,
+    pub end_date: NaiveDate,
+}
+
+fn ymd(y: i32, m: u32, d: u32) -> NaiveDate {
+    NaiveDate::from_ymd_opt(y, m, d).unwrap()
+}
+
+impl Scenario {
+    pub fn new(name: &str, start_date: NaiveDate, end_date: NaiveDate) -> Self {
+        Scenario {
+            name: name.to_string(),
+            start_date,
+            end_date,
+        }
+    }
+
+    /// Global financial crisis, from the top of A-shares in October
+    /// 2007 to the rebound in 2009.
+    pub fn crash_2008() -> Self {
+        Self::new("2008 crash", ymd(2007, 10, 1), ymd(2009, 9, 1))
+    }
+
+    /// Burst of the A-share bubble in June 2015 and the circuit
+    /// breaker in January 2016.
+    pub fn bubble_2015() -> Self {
+        Self::new("2015 bubble burst", ymd(2015, 6, 1), ymd(2017, 1, 1))
+    }
+
+    /// Sino-US trade war through 2018 and the rebound in early 2019.
+    pub fn trade_war_2018() -> Self {
+        Self::new("2018 trade war", ymd(2018, 1, 15), ymd(2019, 5, 1))
+    }
+
+    /// COVID-19 sell-off in early 2020 and the recovery afterwards.
+    pub fn covid_2020() -> Self {
+        Self::new("2020 COVID drop", ymd(2020, 1, 13), ymd(2020, 8, 1))
+    }
+
+    /// All the preset scenarios.
+    pub fn presets() -> Vec<Self> {
+        vec![
+            Self::crash_2008(),
+            Self::bubble_2015(),
+            Self::trade_war_2018(),
+            Self::covid_2020(),
+        ]
+    }
+}
+
+/// Extract the path of `fund` in the window of `scenario`.
+///
+/// Returns the dates in the window and the growth of the fund
+/// relative to the first date.
+pub fn scenario_path(
+    fund: &Fund,
+    scenario: &Scenario,
+) -> Result<(Vec<NaiveDate>, Vec<f64>), StressError> {
+    let data: Vec<_> = fund
+        .data()
+        .iter()
+        .filter(|ds| scenario.start_date <= ds.date() && ds.date() < scenario.end_date)
+        .collect();
+    if data.len() < 2 {
+        return Err(StressError("fund does not cover window of scenario"));
+    }
+    let base = data[0].value();
+    Ok((
+        data.iter().map(|ds| ds.date()).collect(),
+        data.iter().map(|ds| ds.value() / base).collect(),
+    ))
+}
+
+/// How a portfolio goes through a scenario.
+#[derive(Debug, Clone)]
+pub struct StressOutcome {
+    /// Time-weighted equity curve, which starts from 1 at the first
+    /// date of the scenario.
+    pub equity: Vec<f64>,
+    /// Peak-to-trough loss of the equity curve, which ranges from 0
+    /// to 1.
+    pub max_loss: f64,
+    pub peak_date: NaiveDate,
+    pub trough_date: NaiveDate,
+    /// Days from the trough until the equity gets back to the peak,
+    /// or `None` if it does not recover within the scenario.
+    pub recovery_days: Option<i64>,
+    /// Return at the end of the scenario.
+    pub final_return: f64,
+}
+
+impl StressOutcome {
+    fn new(dates: &[NaiveDate], equity: Vec<f64>) -> Self {
+        let (mut peak, mut trough, mut max_loss) = (0, 0, 0.);
+        let mut running_peak = 0;
+        for (i, &v) in equity.iter().enumerate() {
+            if v > equity[running_peak] {
+                running_peak = i;
+            }
+            let loss = 1. - v / equity[running_peak];
+            if loss > max_loss {
+                (peak, trough, max_loss) = (running_peak, i, loss);
+            }
+        }
+        let recovery_days = if max_loss == 0. {
+            Some(0)
+        } else {
+            equity[trough..]
+                .iter()
+                .position(|&v| v >= equity[peak])
+                .map(|k| (dates[trough + k] - dates[trough]).num_days())
+        };
+        StressOutcome {
+            final_return: equity[equity.len() - 1] - 1.,
+            equity,
+            max_loss,
+            peak_date: dates[peak],
+            trough_date: dates[trough],
+            recovery_days,
+        }
+    }
+}
+
+/// Result of replaying a scenario.
+#[derive(Debug, Clone)]
+pub struct StressResult {
+    pub scenario: Scenario,
+    /// Dates of the scenario.
+    pub dates: Vec<NaiveDate>,
+    /// Outcome of holding the portfolio without any trade.
+    pub passive: StressOutcome,
+    /// Outcome of running the strategy on the portfolio.
+    pub strategy: StressOutcome,
+    /// Trades and comments made by the strategy during the scenario,
+    /// dated by the calendar of the scenario.
+    pub reactions: ConciseRecord,
+}
+
+/// Replay the scenario on the iterator, and return its outcome and
+/// the records of trades after the seeding date.
+fn replay<F>(
+    trans: &Transaction,
+    source: &TransactionIterator,
+    dates: &[NaiveDate],
+    offset: Duration,
+    f: Option<&F>,
+) -> Result<(StressOutcome, ConciseRecord), Box<dyn Error>>
+where
+    F: Fn(&mut TransactionIterator) -> Result<(), Box<dyn Error>>,
+{
+    let seed_date = *source.dates().last().unwrap();
+    let mut it = trans.iter(true, true);
+    it.goto(seed_date).unwrap();
+    let seed_idx = it.dates().len();
+    it.inflow_comment(source.asset(), "holdings")?;
+    for j in 0..source.nfunds() {
+        let value = source.fund_asset(j);
+        if value != 0. {
+            it.buy(j, value, 0.)?;
+        }
+    }
+    it.next_day();
+    if let Some(f) = f {
+        f(&mut it)?;
+    }
+    while it.next_day().is_some() {}
+    let equity = equity_curve(&it).unwrap();
+    let equity = equity[seed_idx..]
+        .iter()
+        .map(|x| x / equity[seed_idx])
+        .collect();
+    let mut record = ConciseRecord::new(it.transaction().names().join(", ").as_str(), "");
+    for rs in
+        it.record().unwrap().records().iter().filter(|rs| {
+            rs.date() > seed_date && (rs.investment() != 0. || !rs.comment().is_empty())
+        })
+    {
+        record.append(
+            rs.date() - offset,
+            rs.investment(),
+            rs.present_value(),
+            rs.comment(),
+        );
+    }
+    Ok((StressOutcome::new(dates, equity), record))
+}
+
+/// Replay a historical scenario on the current portfolio.
+///
+/// The holdings of `it` at the beginning of today are carried into
+/// the scenario, and fund `j` follows the returns of `proxies[j]`
+/// during the window of the scenario. The portfolio is held without
+/// any trade for `passive`, and `f` is run from the first day of the
+/// scenario for `strategy`, so that the two outcomes tell how the
+/// strategy reacts to the crisis.
+///
+/// The NAVs of `it` before today are kept as history, and the dates
+/// of the scenario are shifted by whole weeks to follow them, which
+/// keeps weekly schedules of strategies. Dates in the result are
+/// still those of the scenario.
+///
+/// # Arguments
+///
+/// * `it` - The iterator holding the current portfolio.
+/// * `scenario` - The window to replay.
+/// * `proxies` - Fund whose path is applied to each fund of `it`.
+/// * `f` - Function running the strategy.
+pub fn stress_test<F>(
+    it: &TransactionIterator,
+    scenario: &Scenario,
+    proxies: &[&Fund],
+    f: F,
+) -> Result<StressResult, Box<dyn Error>>
+where
+    F: Fn(&mut TransactionIterator) -> Result<(), Box<dyn Error>>,
+{
+    if proxies.len() != it.nfunds() {
+        return Err(Box::new(StressError(
+            "number of proxies should match number of funds",
+        )));
+    }
+    let last_date = *it
+        .dates()
+        .last()
+        .ok_or(StressError("no history before current date"))?;
+    let mut paths = Vec::with_capacity(proxies.len());
+    for proxy in proxies {
+        paths.push(scenario_path(proxy, scenario)?);
+    }
+    let dates = paths[0].0.clone();
+    if paths.iter().any(|(d, _)| *d != dates) {
+        return Err(Box::new(StressError("dates of proxies do not match")));
+    }
+
+    // Shift the scenario by whole weeks so that it starts no earlier
+    // than the last date of history.
+    let gap = (last_date - dates[0]).num_days();
+    let offset = Duration::days((gap + 6).div_euclid(7) * 7);
+    let trans = it.transaction();
+    let navs = it.navs();
+    let funds: Vec<Fund> = (0..it.nfunds())
+        .map(|j| {
+            let mut fund = Fund::new(&trans.names()[j], &trans.codes()[j]);
+            for (t, &date) in it.dates().iter().enumerate() {
+                fund.append(date, navs[[t, j]]);
+            }
+            let last_nav = navs[[navs.nrows() - 1, j]];
+            for (k, &date) in dates.iter().enumerate().skip(1) {
+                fund.append(date + offset, last_nav * paths[j].1[k]);
+            }
+            fund
+        })
+        .collect();
+    let stressed = Transaction::from_funds(&funds.iter().collect::<Vec<_>>());
+
+    let (passive, _) = replay::<F>(&stressed, it, &dates, offset, None)?;
+    let (strategy, reactions) = replay(&stressed, it, &dates, offset, Some(&f))?;
+    Ok(StressResult {
+        scenario: scenario.clone(),
+        dates,
+        passive,
+        strategy,
+        reactions,
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::utility::drawdown;
+    use crate::*;
+
+    #[test]
+    fn test_stress_test() {
+        let hs300 = Fund::from(&read_gta("hs300.txt").unwrap());
+        let gz2000 = Fund::from(&read_gta("gz2000.txt").unwrap());
+        let start_date = NaiveDate::parse_from_str("20220101", "%Y%m%d").unwrap();
+        let trans = Transaction::new(&[&hs300, &gz2000], Some(start_date), None);
+        let mut it = trans.iter(false, false);
+        it.inflow(1000.).unwrap();
+        it.buy(0, 600., 0.).unwrap();
+        it.buy(1, 200., 0.).unwrap();
+        it.goto(NaiveDate::parse_from_str("20230601", "%Y%m%d").unwrap())
+            .unwrap();
+
+        let scenario = Scenario::covid_2020();
+        let result = stress_test(&it, &scenario, &[&hs300, &hs300], |it| {
+            // Cut all the funds once the loss exceeds 5%.
+            let asset = it.asset();
+            loop {
+                if it.asset() < 0.95 * asset {
+                    for j in 0..it.nfunds() {
+                        it.sell_comment(j, it.share(j), 0., "stop loss")?;
+                    }
+                    return Ok(());
+                }
+                if it.next_day().is_none() {
+                    return Ok(());
+                }
+            }
+        })
+        .unwrap();
+
+        let (dates, growth) = scenario_path(&hs300, &scenario).unwrap();
+        assert_eq!(result.dates, dates);
+        assert_eq!(result.passive.equity.len(), dates.len());
+        // The funds follow hs300 and the rest is cash.
+        let weight = (it.fund_asset(0) + it.fund_asset(1)) / it.asset();
+        let expected: Vec<f64> = growth.iter().map(|g| 1. + weight * (g - 1.)).collect();
+        assert!(result
+            .passive
+            .equity
+            .iter()
+            .zip(&expected)
+            .all(|(x, y)| (x - y).abs() < 1e-9));
+        let max_loss = drawdown(&expected).into_iter().fold(0., f64::max);
+        assert!((result.passive.max_loss - max_loss).abs() < 1e-9);
+        assert_eq!(
+            result.passive.trough_date,
+            NaiveDate::parse_from_str("20200323", "%Y%m%d").unwrap()
+        );
+        assert!(result.passive.recovery_days.is_some());
+
+        assert!(result.strategy.max_loss < result.passive.max_loss);
+        assert!(result.strategy.recovery_days.is_none());
+        assert_eq!(result.reactions.len(), 1);
+        assert!(result.reactions[0].comment().contains("stop loss"));
+        assert!(dates.contains(&result.reactions[0].date()));
+    }
+
+    #[test]
+    fn test_stress_test_error() {
+        let hs300 = Fund::from(&read_gta("hs300.txt").unwrap());
+        let trans = Transaction::new(&[&hs300], None, None);
+        let it = trans.iter(false, false);
+        assert!(stress_test(&it, &Scenario::covid_2020(), &[&hs300], |_| Ok(())).is_err());
+        let mut it = trans.iter(false, false);
+        it.next_day();
+        assert!(stress_test(&it, &Scenario::crash_2008(), &[], |_| Ok(())).is_err());
+        for scenario in Scenario::presets() {
+            assert!(scenario_path(&hs300, &scenario).is_ok());
+        }
+    }
+}
@@ -0,0 +1,481 @@
+use std::error::Error;
+use std::io::Write;
+use std::ops::Index;
+use std::panic::{catch_unwind, AssertUnwindSafe};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+
+use crate::analysis::metrics::{Metric, Metrics};
+use crate::export::{csv_field, json_number, json_string, Export};
+use crate::Transaction;
+
+#[derive(Debug)]
+pub struct SweepError(&'static str);
+
+impl std::fmt::Display for SweepError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        writeln!(f, "SweepError: {}", self.0)
+    }
+}
+
+impl std::error::Error for SweepError {}
+
+/// Grid of parameters, whose combinations are swept.
+///
+/// # Examples
+/// ```
+/// use eatmud::analysis::ParamGrid;
+/// let grid = ParamGrid::new()
+///     .add("n", &[1300., 1600.])
+///     .add("risk_bound", &[0.01, 0.02, 0.05]);
+/// assert_eq!(grid.len(), 6);
+/// let params = &grid.combinations()[1];
+/// assert_eq!(params["n"], 1300.);
+/// assert_eq!(params["risk_bound"], 0.02);
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct ParamGrid {
+    names: Vec<String>,
+    values: Vec<Vec<f64>>,
+}
+
+impl ParamGrid {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a parameter and its candidate values to the grid.
+    ///
+    /// Integer or enumerated parameters, such as lookbacks or
+    /// weekdays, can be given as floats and converted back in the
+    /// closure of `sweep`.
+    pub fn add(mut self, name: &str, values: &[f64]) -> Self {
+        self.names.push(name.to_string());
+        self.values.push(values.to_vec());
+        self
+    }
+
+    pub fn names(&self) -> &[String] {
+        &self.names
+    }
+
+    /// Number of combinations.
+    pub fn len(&self) -> usize {
+        if self.names.is_empty() {
+            0
+        } else {
+            self.values.iter().map(|v| v.len()).product()
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// All combinations of the parameters, where the last parameter
+    /// varies the fastest.
+    pub fn combinations(&self) -> Vec<ParamSet> {
+        let mut res = Vec::with_capacity(self.len());
+        for k in 0..self.len() {
+            let mut values = vec![0.; self.names.len()];
+            let mut rest = k;
+            for (j, candidates) in self.values.iter().enumerate().rev() {
+                values[j] = candidates[rest % candidates.len()];
+                rest /= candidates.len();
+            }
+            res.push(ParamSet {
+                names: self.names.clone(),
+                values,
+            });
+        }
+        res
+    }
+}
+
+/// One combination of parameters of `ParamGrid`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ParamSet {
+    names: Vec<String>,
+    values: Vec<f64>,
+}
+
+impl ParamSet {
+    pub fn names(&self) -> &[String] {
+        &self.names
+    }
+
+    pub fn values(&self) -> &[f64] {
+        &self.values
+    }
+
+    /// Get the value of a parameter by its name.
+    pub fn get(&self, name: &str) -> Option<f64> {
+        let j = self.names.iter().position(|x| x == name)?;
+        Some(self.values[j])
+    }
+}
+
+impl Index<&str> for ParamSet {
+    type Output = f64;
+    fn index(&self, name: &str) -> &f64 {
+        let j = self
+            .names
+            .iter()
+            .position(|x| x == name)
+            .unwrap_or_else(|| panic!("parameter {} not found", name));
+        &self.values[j]
+    }
+}
+
+impl std::fmt::Display for ParamSet {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let items: Vec<String> = self
+            .names
+            .iter()
+            .zip(&self.values)
+            .map(|(name, value)| format!("{}={}", name, value))
+            .collect();
+        write!(f, "{}", items.join(", "))
+    }
+}
+
+/// Result of one combination in `SweepTable`.
+#[derive(Debug, Clone)]
+pub struct SweepRow {
+    pub params: ParamSet,
+    /// Metrics of the backtest, or the error message if it fails.
+    pub metrics: Result<Metrics, String>,
+}
+
+/// Table of parameter sets against their metrics.
+#[derive(Debug, Clone)]
+pub struct SweepTable {
+    names: Vec<String>,
+    rows: Vec<SweepRow>,
+}
+
+impl SweepTable {
+    /// Names of the parameters.
+    pub fn names(&self) -> &[String] {
+        &self.names
+    }
+
+    pub fn rows(&self) -> &[SweepRow] {
+        &self.rows
+    }
+
+    pub fn len(&self) -> usize {
+        self.rows.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rows.is_empty()
+    }
+
+    /// Sort the rows from the best to the worst by `metric`.
+    ///
+    /// Failed backtests are put at the end. The sort is stable, so
+    /// ties keep the order of the grid.
+    pub fn sort_by(&mut self, metric: Metric) {
+        self.rows.sort_by(|a, b| {
+            let score = |row: &SweepRow| {
+                row.metrics
+                    .as_ref()
+                    .map_or(f64::NEG_INFINITY, |m| metric.score(m))
+            };
+            score(b).total_cmp(&score(a))
+        });
+    }
+
+    /// The best row by `metric`, or `None` if all backtests fail.
+    pub fn best(&self, metric: Metric) -> Option<&SweepRow> {
+        self.rows
+            .iter()
+            .filter_map(|row| Some((row, metric.score(row.metrics.as_ref().ok()?))))
+            .fold(
+                None,
+                |best: Option<(&SweepRow, f64)>, (row, score)| match best {
+                    Some((_, s)) if s >= score => best,
+                    _ => Some((row, score)),
+                },
+            )
+            .map(|(row, _)| row)
+    }
+}
+
+impl Export for SweepTable {
+    /// Write the table as CSV with one row per parameter set.
+    ///
+    /// Columns are the parameters, the metrics and `error`. Metrics
+    /// of failed backtests are left empty.
+    fn write_csv<W: Write>(&self, writer: &mut W) -> Result<(), Box<dyn Error>> {
+        let mut header: Vec<String> = self
+            .names
+            .iter()
+            .map(|name| csv_field(name).into_owned())
+            .collect();
+        header.extend(Metric::ALL.iter().map(|m| m.name().to_string()));
+        header.push("error".to_string());
+        writeln!(writer, "{}", header.join(","))?;
+        for row in &self.rows {
+            for value in row.params.values() {
+                write!(writer, "{},", value)?;
+            }
+            match &row.metrics {
+                Ok(metrics) => {
+                    for metric in Metric::ALL {
+                        write!(writer, "{},", metric.value(metrics))?;
+                    }
+                    writeln!(writer)?;
+                }
+                Err(error) => {
+                    writeln!(
+                        writer,
+                        "{}{}",
+                        ",".repeat(Metric::ALL.len()),
+                        csv_field(error)
+                    )?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Write the table as a JSON array of objects.
+    ///
+    /// Each object has the keys `params` and `metrics`, which are
+    /// objects keyed by the names, and `error`. `metrics` is `null`
+    /// for failed backtests and `error` is `null` otherwise.
+    fn write_json<W: Write>(&self, writer: &mut W) -> Result<(), Box<dyn Error>> {
+        write!(writer, "[")?;
+        for (i, row) in self.rows.iter().enumerate() {
+            if i != 0 {
+                write!(writer, ",")?;
+            }
+            let params: Vec<String> = self
+                .names
+                .iter()
+                .zip(row.params.values())
+                .map(|(name, &value)| format!("{}:{}", json_string(name), json_number(value)))
+                .collect();
+            write!(writer, "{{\"params\":{{{}}},", params.join(","))?;
+            match &row.metrics {
+                Ok(metrics) => {
+                    let values: Vec<String> = Metric::ALL
+                        .iter()
+                        .map(|m| format!("\"{}\":{}", m.name(), json_number(m.value(metrics))))
+                        .collect();
+                    write!(
+                        writer,
+                        "\"metrics\":{{{}}},\"error\":null}}",
+                        values.join(",")
+                    )?;
+                }
+                Err(error) => {
+                    write!(
+                        writer,
+                        "\"metrics\":null,\"error\":{}}}",
+                        json_string(error)
+                    )?;
+                }
+            }
+        }
+        writeln!(writer, "]")?;
+        Ok(())
+    }
+}
+
+/// Message of a panic caught from a backtest.
+fn panic_message(payload: &(dyn std::any::Any + Send)) -> String {
+    let message = if let Some(s) = payload.downcast_ref::<&str>() {
+        s
+    } else if let Some(s) = payload.downcast_ref::<String>() {
+        s.as_str()
+    } else {
+        "unknown error"
+    };
+    format!("backtest panicked: {}", message)
+}
+
+/// Run a backtest for each combination of `grid` in parallel.
+///
+/// `f` builds and runs a strategy on the shared transaction with the
+/// given parameters, and evaluates it by `Metrics::new`. Since the
+/// transaction is read-only, the backtests run on `nthreads` threads
+/// without copying it. The rows of the returned table are in the order
+/// of `ParamGrid::combinations` regardless of the threads. A backtest
+/// which returns an error or panics is kept as a failed row, and the
+/// other backtests go on.
+///
+/// # Arguments
+///
+/// * `trans` - The transaction shared by the backtests.
+/// * `grid` - The parameters to sweep.
+/// * `nthreads` - Number of threads. Use 0 for the available
+///   parallelism of the system.
+/// * `f` - Function running a backtest with a parameter set.
+///
+/// # Examples
+/// ```
+/// use eatmud::{read_gta, Fund, NaiveDate, Transaction};
+/// use eatmud::analysis::{sweep, Metric, Metrics, ParamGrid};
+/// let hs300 = Fund::from(&read_gta("hs300.txt").unwrap());
+/// let start_date = NaiveDate::parse_from_str("2020-01-01", "%Y-%m-%d").unwrap();
+/// let trans = Transaction::new(&[&hs300], Some(start_date), None);
+/// let grid = ParamGrid::new().add("day", &[1., 10., 20.]);
+/// let mut table = sweep(&trans, &grid, 0, |trans, params| {
+///     let mut it = trans.iter(true, true);
+///     let day = params["day"] as u32;
+///     eatmud::strategy::aip_monthly(&mut it, day, &[1000.], &[0.])?;
+///     Ok(Metrics::new(&it)?)
+/// })
+/// .unwrap();
+/// assert!(table.rows().iter().all(|row| row.metrics.is_ok()));
+/// let best = table.best(Metric::Irr).unwrap().params.clone();
+/// table.sort_by(Metric::Irr);
+/// assert_eq!(table.rows()[0].params, best);
+/// ```
+pub fn sweep<F>(
+    trans: &Transaction,
+    grid: &ParamGrid,
+    nthreads: usize,
+    f: F,
+) -> Result<SweepTable, Box<dyn Error>>
+where
+    F: Fn(&Transaction, &ParamSet) -> Result<Metrics, Box<dyn Error>> + Sync,
+{
+    if grid.is_empty() {
+        return Err(Box::new(SweepError("parameter grid is empty")));
+    }
+    let nthreads = if nthreads == 0 {
+        std::thread::available_parallelism().map_or(1, |n| n.get())
+    } else {
+        nthreads
+    };
+    let combinations = grid.combinations();
+    let results: Mutex<Vec<Option<Result<Metrics, String>>>> =
+        Mutex::new(vec![None; combinations.len()]);
+    let next = AtomicUsize::new(0);
+    std::thread::scope(|scope| {
+        for _ in 0..nthreads.min(combinations.len()) {
+            scope.spawn(|| loop {
+                let k = next.fetch_add(1, Ordering::Relaxed);
+                let Some(params) = combinations.get(k) else {
+                    break;
+                };
+                let metrics = match catch_unwind(AssertUnwindSafe(|| f(trans, params))) {
+                    Ok(metrics) => metrics.map_err(|e| e.to_string().trim_end().to_string()),
+                    Err(payload) => Err(panic_message(payload.as_ref())),
+                };
+                results.lock().unwrap()[k] = Some(metrics);
+            });
+        }
+    });
+    let rows = combinations
+        .into_iter()
+        .zip(results.into_inner().unwrap())
+        .map(|(params, metrics)| SweepRow {
+            params,
+            metrics: metrics.unwrap(),
+        })
+        .collect();
+    Ok(SweepTable {
+        names: grid.names().to_vec(),
+        rows,
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::*;
+
+    #[test]
+    fn test_sweep() {
+        let hs300 = Fund::from(&read_gta("hs300.txt").unwrap());
+        let gz2000 = Fund::from(&read_gta("gz2000.txt").unwrap());
+        let start_date = NaiveDate::parse_from_str("20150101", "%Y%m%d").unwrap();
+        let end_date = NaiveDate::parse_from_str("20240101", "%Y%m%d").unwrap();
+        let trans = Transaction::new(&[&hs300, &gz2000], Some(start_date), Some(end_date));
+        let grid = ParamGrid::new()
+            .add("weekday", &[0., 1., 2., 3., 4.])
+            .add("risk_bound", &[0.01, 0.05]);
+        let run = |trans: &Transaction, params: &ParamSet| -> Result<Metrics, Box<dyn Error>> {
+            let weekday = Weekday::try_from(params["weekday"] as u8)?;
+            let risk_bound = params["risk_bound"];
+            if risk_bound > 0.02 && weekday == Weekday::Fri {
+                return Err(Box::new(SweepError("skipped")));
+            }
+            let mut it = trans.iter(true, true);
+            it.goto(NaiveDate::parse_from_str("20180101", "%Y%m%d").unwrap());
+            it.inflow(1.)?;
+            strategy::kelly_weekly(
+                &mut it,
+                weekday,
+                &[500, 500],
+                &[0.015, 0.015],
+                &[risk_bound, risk_bound],
+            )?;
+            Ok(Metrics::new(&it)?)
+        };
+        let mut table = sweep(&trans, &grid, 4, run).unwrap();
+        assert_eq!(table.len(), 10);
+        // Same as running sequentially.
+        for row in table.rows() {
+            match run(&trans, &row.params) {
+                Ok(metrics) => assert_eq!(row.metrics, Ok(metrics)),
+                Err(_) => assert_eq!(row.metrics, Err("SweepError: skipped".to_string())),
+            }
+        }
+
+        let best = table.best(Metric::FinalAsset).unwrap().clone();
+        table.sort_by(Metric::FinalAsset);
+        assert_eq!(table.rows()[0].params, best.params);
+        assert!(table.rows()[9].metrics.is_err());
+        let assets: Vec<f64> = table.rows()[..9]
+            .iter()
+            .map(|row| row.metrics.as_ref().unwrap().final_asset)
+            .collect();
+        assert!(assets.windows(2).all(|w| w[0] >= w[1]));
+
+        let mut buffer = Vec::new();
+        table.write_csv(&mut buffer).unwrap();
+        let text = String::from_utf8(buffer).unwrap();
+        let lines: Vec<&str> = text.lines().collect();
+        assert_eq!(lines.len(), 11);
+        assert_eq!(
+            lines[0],
+            "weekday,risk_bound,final_asset,total_return,irr,max_drawdown,error"
+        );
+        assert_eq!(lines[10], "4,0.05,,,,,SweepError: skipped");
+        let mut buffer = Vec::new();
+        table.write_json(&mut buffer).unwrap();
+        let text = String::from_utf8(buffer).unwrap();
+        assert!(text.starts_with("[{\"params\":{\"weekday\":"));
+        assert!(text.ends_with("\"metrics\":null,\"error\":\"SweepError: skipped\"}]\n"));
+    }
+
+    #[test]
+    fn test_sweep_panic() {
+        let hs300 = Fund::from(&read_gta("hs300.txt").unwrap());
+        let start_date = NaiveDate::parse_from_str("20200101", "%Y%m%d").unwrap();
+        let trans = Transaction::new(&[&hs300], Some(start_date), None);
+        let grid = ParamGrid::new().add("day", &[1., 0., 20.]);
+        let table = sweep(&trans, &grid, 2, |trans, params| {
+            let day = params["day"] as u32;
+            if day == 0 {
+                panic!("invalid day");
+            }
+            let mut it = trans.iter(true, true);
+            strategy::aip_monthly(&mut it, day, &[1000.], &[0.])?;
+            Ok(Metrics::new(&it)?)
+        })
+        .unwrap();
+        assert!(table.rows()[0].metrics.is_ok());
+        assert_eq!(
+            table.rows()[1].metrics,
+            Err("backtest panicked: invalid day".to_string())
+        );
+        assert!(table.rows()[2].metrics.is_ok());
+    }
+}
@@ -0,0 +1,327 @@
+use std::error::Error;
+
+use chrono::{Duration, NaiveDate};
+
+use crate::analysis::metrics::{equity_curve, Metric, Metrics};
+use crate::analysis::sweep::{sweep, ParamGrid, ParamSet};
+use crate::record::RecordSlice;
+use crate::{ConciseRecord, Transaction, TransactionIterator};
+
+#[derive(Debug)]
+pub struct WalkForwardError(&'static str);
+
+impl std::fmt::Display for WalkForwardError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        writeln!(f, "WalkForwardError: {}", self.0)
+    }
+}
+
+impl std::error::Error for WalkForwardError {}
+
+/// How in-sample windows move forward.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WindowMode {
+    /// In-sample windows keep their length and move by the length of
+    /// out-of-sample windows.
+    Rolling,
+    /// In-sample windows start from the first date and grow by the
+    /// length of out-of-sample windows.
+    Anchored,
+}
+
+/// Parameters of walk-forward analysis.
+#[derive(Debug, Clone)]
+pub struct WalkForward {
+    /// Start of the first in-sample window. Defaults to the start date
+    /// of the transaction. Dates before it are kept as history for
+    /// the strategies.
+    pub start_date: Option<NaiveDate>,
+    /// Length of the (first) in-sample window.
+    pub in_sample: Duration,
+    /// Length of each out-of-sample window.
+    pub out_of_sample: Duration,
+    pub mode: WindowMode,
+    /// Metric to choose the parameters in each in-sample window.
+    pub metric: Metric,
+}
+
+/// Dates of an in-sample window followed by its out-of-sample window.
+///
+/// Each window includes its start date and excludes its end date, and
+/// the out-of-sample window starts at the end of the in-sample one.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Window {
+    pub in_sample_start: NaiveDate,
+    pub in_sample_end: NaiveDate,
+    pub out_of_sample_end: NaiveDate,
+}
+
+impl WalkForward {
+    /// Split the date range of `trans` into windows.
+    ///
+    /// The last out-of-sample window is truncated at the end date of
+    /// `trans`, and windows without any trading day in the
+    /// out-of-sample part are skipped.
+    pub fn windows(&self, trans: &Transaction) -> Vec<Window> {
+        let start_date = self.start_date.unwrap_or(trans.start_date());
+        let mut windows = Vec::new();
+        let mut in_sample_end = start_date + self.in_sample;
+        while in_sample_end < trans.end_date() {
+            let in_sample_start = match self.mode {
+                WindowMode::Rolling => in_sample_end - self.in_sample,
+                WindowMode::Anchored => start_date,
+            };
+            let out_of_sample_end =
+                NaiveDate::min(in_sample_end + self.out_of_sample, trans.end_date());
+            // Skip the window without any trading day.
+            let idx = trans.date().partition_point(|d| *d < in_sample_end);
+            if trans
+                .date()
+                .get(idx)
+                .is_some_and(|d| *d < out_of_sample_end)
+            {
+                windows.push(Window {
+                    in_sample_start,
+                    in_sample_end,
+                    out_of_sample_end,
+                });
+            }
+            in_sample_end += self.out_of_sample;
+        }
+        windows
+    }
+}
+
+/// Result of one window of walk-forward analysis.
+#[derive(Debug, Clone)]
+pub struct WalkForwardSegment {
+    pub window: Window,
+    /// Parameters chosen in the in-sample window.
+    pub params: ParamSet,
+    pub in_sample_metrics: Metrics,
+    pub out_of_sample_metrics: Metrics,
+}
+
+/// Result of walk-forward analysis.
+#[derive(Debug, Clone)]
+pub struct WalkForwardResult {
+    pub segments: Vec<WalkForwardSegment>,
+    /// Dates of all the out-of-sample windows.
+    pub dates: Vec<NaiveDate>,
+    /// Time-weighted equity curve stitched by the out-of-sample
+    /// windows, which starts from 1.
+    pub equity: Vec<f64>,
+    /// Records of the out-of-sample windows. The portfolio is
+    /// withdrawn at the end of each window, so that the IRR of the
+    /// record is the IRR of all the windows.
+    pub record: ConciseRecord,
+}
+
+/// Run `f` from `start_date` to the end of `trans` with full history
+/// before.
+///
+/// Returns the iterator together with the index of `start_date`.
+pub(crate) fn run_window<'a, F>(
+    trans: &'a Transaction,
+    start_date: NaiveDate,
+    params: &ParamSet,
+    f: &F,
+) -> Result<(TransactionIterator<'a>, usize), Box<dyn Error>>
+where
+    F: Fn(&mut TransactionIterator, &ParamSet) -> Result<(), Box<dyn Error>>,
+{
+    let mut it = trans.iter(true, true);
+    it.goto(start_date)
+        .ok_or(WalkForwardError("window is out of range of transaction"))?;
+    let start_idx = it.dates().len();
+    f(&mut it, params)?;
+    while it.next_day().is_some() {}
+    Ok((it, start_idx))
+}
+
+/// Walk-forward analysis.
+///
+/// For each window, the strategy is run with every combination of
+/// `grid` in the in-sample window by `sweep`, and the best parameters
+/// by `config.metric` are run in the following out-of-sample window.
+/// The out-of-sample windows are stitched into one equity curve and
+/// record, which tell how the optimized strategy performs on data it
+/// has not seen.
+///
+/// `f` runs the strategy from the current date of the iterator, which
+/// is the start of a window, and the transaction is truncated at the
+/// end of the window. NAVs before the window are kept, so strategies
+/// with lookbacks can start trading from the first day. Cash should be
+/// provided by `inflow` in `f`.
+///
+/// # Arguments
+///
+/// * `trans` - The transaction to analyse.
+/// * `grid` - The parameters to optimize.
+/// * `config` - Windows and the metric of optimization.
+/// * `nthreads` - Number of threads for `sweep`.
+/// * `f` - Function running the strategy with a parameter set.
+pub fn walk_forward<F>(
+    trans: &Transaction,
+    grid: &ParamGrid,
+    config: &WalkForward,
+    nthreads: usize,
+    f: F,
+) -> Result<WalkForwardResult, Box<dyn Error>>
+where
+    F: Fn(&mut TransactionIterator, &ParamSet) -> Result<(), Box<dyn Error>> + Sync,
+{
+    if config.in_sample <= Duration::zero() || config.out_of_sample <= Duration::zero() {
+        return Err(Box::new(WalkForwardError(
+            "lengths of windows should be positive",
+        )));
+    }
+    let windows = config.windows(trans);
+    if windows.is_empty() {
+        return Err(Box::new(WalkForwardError(
+            "in-sample window exceeds transaction",
+        )));
+    }
+    let mut segments = Vec::with_capacity(windows.len());
+    let mut dates = Vec::new();
+    let mut equity = Vec::new();
+    let mut record = ConciseRecord::new("Walk Forward", "");
+    for window in windows {
+        let in_sample = trans.slice(None, Some(window.in_sample_end));
+        let table = sweep(&in_sample, grid, nthreads, |trans, params| {
+            let (it, _) = run_window(trans, window.in_sample_start, params, &f)?;
+            Ok(Metrics::new(&it)?)
+        })?;
+        let best = table
+            .best(config.metric)
+            .ok_or(WalkForwardError("all backtests fail in in-sample window"))?;
+        let params = best.params.clone();
+        let in_sample_metrics = *best.metrics.as_ref().unwrap();
+
+        let out_of_sample = trans.slice(None, Some(window.out_of_sample_end));
+        let (it, start_idx) = run_window(&out_of_sample, window.in_sample_end, &params, &f)?;
+        let segment_equity = equity_curve(&it).unwrap();
+        let last = equity.last().copied().unwrap_or(1.);
+        dates.extend_from_slice(&it.dates()[start_idx..]);
+        equity.extend(
+            segment_equity[start_idx..]
+                .iter()
+                .map(|x| last * x / segment_equity[start_idx]),
+        );
+        let segment_record = it.record().unwrap();
+        for rs in segment_record
+            .records()
+            .iter()
+            .filter(|rs| rs.date() >= window.in_sample_end)
+        {
+            record.append(
+                rs.date(),
+                rs.investment(),
+                rs.present_value(),
+                &format!("[{}] {}", params, rs.comment()),
+            );
+        }
+        let out_of_sample_metrics = Metrics::new(&it)?;
+        record.append(
+            it.today(),
+            -out_of_sample_metrics.final_asset,
+            0.,
+            &format!("[{}] end of window", params),
+        );
+        segments.push(WalkForwardSegment {
+            window,
+            params,
+            in_sample_metrics,
+            out_of_sample_metrics,
+        });
+    }
+    Ok(WalkForwardResult {
+        segments,
+        dates,
+        equity,
+        record,
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::*;
+
+    #[test]
+    fn test_windows() {
+        let hs300 = Fund::from(&read_gta("hs300.txt").unwrap());
+        let start_date = NaiveDate::parse_from_str("20150101", "%Y%m%d").unwrap();
+        let end_date = NaiveDate::parse_from_str("20200101", "%Y%m%d").unwrap();
+        let trans = Transaction::new(&[&hs300], Some(start_date), Some(end_date));
+        let mut config = WalkForward {
+            start_date: None,
+            in_sample: Duration::days(731),
+            out_of_sample: Duration::days(365),
+            mode: WindowMode::Rolling,
+            metric: Metric::Irr,
+        };
+        let windows = config.windows(&trans);
+        assert_eq!(windows.len(), 3);
+        for w in windows.windows(2) {
+            assert_eq!(w[0].out_of_sample_end, w[1].in_sample_end);
+            assert_eq!(w[1].in_sample_end - w[1].in_sample_start, config.in_sample);
+        }
+        assert_eq!(windows[2].out_of_sample_end, end_date);
+        config.mode = WindowMode::Anchored;
+        assert!(config
+            .windows(&trans)
+            .iter()
+            .all(|w| w.in_sample_start == start_date));
+    }
+
+    #[test]
+    fn test_walk_forward() {
+        let hs300 = Fund::from(&read_gta("hs300.txt").unwrap());
+        let gz2000 = Fund::from(&read_gta("gz2000.txt").unwrap());
+        let end_date = NaiveDate::parse_from_str("20240101", "%Y%m%d").unwrap();
+        let trans = Transaction::new(&[&hs300, &gz2000], None, Some(end_date));
+        let config = WalkForward {
+            start_date: Some(NaiveDate::parse_from_str("20160101", "%Y%m%d").unwrap()),
+            in_sample: Duration::days(1095),
+            out_of_sample: Duration::days(365),
+            mode: WindowMode::Rolling,
+            metric: Metric::FinalAsset,
+        };
+        let grid = ParamGrid::new().add("n", &[500., 1000.]);
+        let result = walk_forward(&trans, &grid, &config, 0, |it, params| {
+            let n = params["n"] as usize;
+            it.inflow(1.)?;
+            strategy::kelly_weekly(it, Weekday::Mon, &[n, n], &[0.015; 2], &[0.01; 2])
+        })
+        .unwrap();
+        assert_eq!(result.segments.len(), 5);
+        assert_eq!(result.dates.len(), result.equity.len());
+        assert!(result.dates.windows(2).all(|d| d[0] < d[1]));
+        assert_eq!(
+            result.dates[0],
+            *trans
+                .date()
+                .iter()
+                .find(|&&d| d >= result.segments[0].window.in_sample_end)
+                .unwrap()
+        );
+        // The stitched curve is the product of the growth of each
+        // segment, since one unit is invested at the start of each
+        // window and nothing else flows in.
+        let growth: f64 = result
+            .segments
+            .iter()
+            .map(|s| s.out_of_sample_metrics.final_asset)
+            .product();
+        assert!((result.equity[result.equity.len() - 1] - growth).abs() < 1e-9);
+        let record = &result.record;
+        let withdrawn: f64 = result
+            .segments
+            .iter()
+            .map(|s| s.out_of_sample_metrics.final_asset)
+            .sum();
+        assert!((record[record.len() - 1].total_investment() - (5. - withdrawn)).abs() < 1e-9);
+        assert!(record[0].comment().starts_with("[n="));
+    }
+}
@@ -180,8 +180,11 @@ impl std::fmt::Display for ReadDataError {
 
 impl std::error::Error for ReadDataError {}
 
-/// Read stock data from GuoTaiAn's txt output file.
-pub fn read_gta(path: &str) -> Result<Stock, Box<dyn Error>> {
+/// Read GuoTaiAn's txt output file, which is encoded in GBK.
+///
+/// Returns the name and the code in the header, and the following
+/// lines of data.
+fn read_gta_lines(path: &str) -> Result<(String, String, Vec<String>), Box<dyn Error>> {
     let file = fs::File::open(path)?;
     let mut reader = io::BufReader::new(file);
 
@@ -197,8 +200,9 @@ pub fn read_gta(path: &str) -> Result<Stock, Box<dyn Error>> {
         )));
     };
     let code = &code.to_string()[1..code.len() - 1];
-    let mut stock = Data::<StockSlice>::new(name, code);
+    let (name, code) = (name.to_string(), code.to_string());
 
+    let mut lines = Vec::new();
     while let Ok(size) = reader.read_until(b'\n', &mut buffer) {
         if size == 0 {
             break;
@@ -206,7 +210,17 @@ pub fn read_gta(path: &str) -> Result<Stock, Box<dyn Error>> {
         let (results, _encoding, _error) = encoding_rs::GBK.decode(&buffer);
         let line = results.to_string();
         buffer.clear();
+        lines.push(line);
+    }
+    Ok((name, code, lines))
+}
 
+/// Read stock data from GuoTaiAn's txt output file.
+pub fn read_gta(path: &str) -> Result<Stock, Box<dyn Error>> {
+    let (name, code, lines) = read_gta_lines(path)?;
+    let mut stock = Data::<StockSlice>::new(&name, &code);
+
+    for line in lines {
         let mut iter_words = line.split_whitespace();
         let Some(date) = iter_words.next() else {
             continue;
@@ -250,6 +264,43 @@ pub fn read_gta(path: &str) -> Result<Stock, Box<dyn Error>> {
     Ok(stock)
 }
 
+/// Column index of PE percentile in GuoTaiAn's txt output file.
+pub const GTA_PE_PERCENTILE: usize = 21;
+
+/// Read one column of indicators from GuoTaiAn's txt output file.
+///
+/// Columns are separated by tabs, and `column` is the index of the
+/// column where the date is column 0 and the close price is column
+/// 4. Lines with blank or invalid values in the column are skipped.
+///
+/// # Examples
+/// ```
+/// use eatmud::data::{read_gta_column, GTA_PE_PERCENTILE};
+/// let pe = read_gta_column("hs300.txt", GTA_PE_PERCENTILE).unwrap();
+/// assert_eq!(pe.code(), "000300");
+/// assert_eq!(pe[pe.len() - 1].value, 8.45);
+/// ```
+pub fn read_gta_column(path: &str, column: usize) -> Result<Fund, Box<dyn Error>> {
+    let (name, code, lines) = read_gta_lines(path)?;
+    let mut fund = Fund::new(&name, &code);
+
+    for line in lines {
+        let words = line.split('\t').collect::<Vec<_>>();
+        let (Some(date), Some(value)) = (words.first(), words.get(column)) else {
+            continue;
+        };
+        let Ok(date): Result<NaiveDate, _> = NaiveDate::parse_from_str(date.trim(), "%Y/%m/%d")
+        else {
+            continue;
+        };
+        let Ok(value): Result<f64, _> = value.trim().parse() else {
+            continue;
+        };
+        fund.append(date, value);
+    }
+    Ok(fund)
+}
+
 #[cfg(test)]
 mod test {
     use chrono::Days;
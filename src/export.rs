@@ -0,0 +1,353 @@
+use std::borrow::Cow;
+use std::error::Error;
+use std::io::Write;
+
+use crate::record::{ConciseRecordSlice, DetailedRecordSlice, Record, RecordSlice};
+use crate::TransactionIterator;
+
+#[derive(Debug)]
+pub struct ExportError(&'static str);
+
+impl std::fmt::Display for ExportError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        writeln!(f, "Export Error: {}", self.0)
+    }
+}
+
+impl std::error::Error for ExportError {}
+
+/// Write objects as CSV or JSON.
+///
+/// Unlike `Display`, all fields are written with full precision and
+/// comments are never truncated, so that the output can be loaded
+/// into spreadsheets or pandas without loss of information.
+pub trait Export {
+    fn write_csv<W: Write>(&self, writer: &mut W) -> Result<(), Box<dyn Error>>;
+    fn write_json<W: Write>(&self, writer: &mut W) -> Result<(), Box<dyn Error>>;
+}
+
+/// Fields of a record slice which can be exported.
+trait ExportSlice: RecordSlice {
+    const HEADER: &'static [&'static str];
+    /// Numeric fields following `date` and preceding `comment`.
+    fn numbers(&self) -> Vec<f64>;
+}
+
+impl ExportSlice for ConciseRecordSlice {
+    const HEADER: &'static [&'static str] = &[
+        "date",
+        "investment",
+        "present_value",
+        "total_investment",
+        "profit",
+        "comment",
+    ];
+    fn numbers(&self) -> Vec<f64> {
+        vec![
+            self.investment(),
+            self.present_value(),
+            self.total_investment(),
+            self.profit(),
+        ]
+    }
+}
+
+impl ExportSlice for DetailedRecordSlice {
+    const HEADER: &'static [&'static str] = &[
+        "date",
+        "investment",
+        "nav",
+        "share",
+        "fee",
+        "total_investment",
+        "total_share",
+        "present_value",
+        "profit",
+        "comment",
+    ];
+    fn numbers(&self) -> Vec<f64> {
+        vec![
+            self.investment(),
+            self.nav(),
+            self.share(),
+            self.fee(),
+            self.total_investment(),
+            self.total_share(),
+            self.present_value(),
+            self.profit(),
+        ]
+    }
+}
+
+impl<Rs: ExportSlice> Export for Record<Rs> {
+    /// Write the record as CSV with one row per record slice.
+    ///
+    /// # Examples
+    /// ```
+    /// use chrono::NaiveDate;
+    /// use eatmud::{export::Export, ConciseRecord};
+    /// let mut record = ConciseRecord::new("hs300", "123456");
+    /// let date = NaiveDate::parse_from_str("2024-01-01", "%Y-%m-%d").unwrap();
+    /// record.append(date, 10., 10.5, "buy, \"long\" comment");
+    /// let mut buffer = Vec::new();
+    /// record.write_csv(&mut buffer).unwrap();
+    /// let text = String::from_utf8(buffer).unwrap();
+    /// assert_eq!(
+    ///     text,
+    ///     "date,investment,present_value,total_investment,profit,comment\n\
+    ///      2024-01-01,10,10.5,10,0.5,\"buy, \"\"long\"\" comment\"\n"
+    /// );
+    /// ```
+    fn write_csv<W: Write>(&self, writer: &mut W) -> Result<(), Box<dyn Error>> {
+        writeln!(writer, "{}", Rs::HEADER.join(","))?;
+        for rs in self.records() {
+            write!(writer, "{}", rs.date())?;
+            for x in rs.numbers() {
+                write!(writer, ",{}", x)?;
+            }
+            writeln!(writer, ",{}", csv_field(rs.comment()))?;
+        }
+        Ok(())
+    }
+
+    /// Write the record as a JSON object.
+    ///
+    /// The object has the keys `name`, `code`, `comment` and
+    /// `records`, where `records` is an array of objects keyed by
+    /// the field names of the record slices.
+    fn write_json<W: Write>(&self, writer: &mut W) -> Result<(), Box<dyn Error>> {
+        write!(
+            writer,
+            "{{\"name\":{},\"code\":{},\"comment\":{},\"records\":[",
+            json_string(self.name()),
+            json_string(self.code()),
+            json_string(self.comment())
+        )?;
+        let nfields = Rs::HEADER.len();
+        for (i, rs) in self.records().iter().enumerate() {
+            if i != 0 {
+                write!(writer, ",")?;
+            }
+            write!(writer, "{{\"{}\":\"{}\"", Rs::HEADER[0], rs.date())?;
+            for (key, x) in Rs::HEADER[1..nfields - 1].iter().zip(rs.numbers()) {
+                write!(writer, ",\"{}\":{}", key, json_number(x))?;
+            }
+            write!(
+                writer,
+                ",\"{}\":{}}}",
+                Rs::HEADER[nfields - 1],
+                json_string(rs.comment())
+            )?;
+        }
+        writeln!(writer, "]}}")?;
+        Ok(())
+    }
+}
+
+impl Export for TransactionIterator<'_> {
+    /// Write the daily logs of the iteration as CSV.
+    ///
+    /// Columns are `date`, `cash`, the share and asset of each fund
+    /// (suffixed by the fund name) and the total `asset`. Log must
+    /// be enabled for the iterator.
+    fn write_csv<W: Write>(&self, writer: &mut W) -> Result<(), Box<dyn Error>> {
+        let log = IterLogTable::new(self)?;
+        let mut header = vec![Cow::from("date"), Cow::from("cash")];
+        for name in self.transaction().names() {
+            header.push(csv_field(&format!("share_{}", name)).into_owned().into());
+        }
+        for name in self.transaction().names() {
+            header.push(csv_field(&format!("asset_{}", name)).into_owned().into());
+        }
+        header.push(Cow::from("asset"));
+        writeln!(writer, "{}", header.join(","))?;
+        for i in 0..log.dates.len() {
+            write!(writer, "{},{}", log.dates[i], log.cash[i])?;
+            for shares in &log.shares {
+                write!(writer, ",{}", shares[i])?;
+            }
+            for assets in &log.fund_assets {
+                write!(writer, ",{}", assets[i])?;
+            }
+            writeln!(writer, ",{}", log.asset[i])?;
+        }
+        Ok(())
+    }
+
+    /// Write the daily logs of the iteration as JSON.
+    ///
+    /// The output is an array of objects, one for each day iterated,
+    /// with the keys `date`, `cash`, `shares`, `fund_assets` and
+    /// `asset`. `shares` and `fund_assets` are objects keyed by fund
+    /// names. Log must be enabled for the iterator.
+    fn write_json<W: Write>(&self, writer: &mut W) -> Result<(), Box<dyn Error>> {
+        let log = IterLogTable::new(self)?;
+        let names: Vec<_> = self
+            .transaction()
+            .names()
+            .iter()
+            .map(|name| json_string(name))
+            .collect();
+        write!(writer, "[")?;
+        for i in 0..log.dates.len() {
+            if i != 0 {
+                write!(writer, ",")?;
+            }
+            write!(
+                writer,
+                "{{\"date\":\"{}\",\"cash\":{},\"shares\":{{",
+                log.dates[i],
+                json_number(log.cash[i])
+            )?;
+            for (j, shares) in log.shares.iter().enumerate() {
+                let sep = if j == 0 { "" } else { "," };
+                write!(writer, "{}{}:{}", sep, names[j], json_number(shares[i]))?;
+            }
+            write!(writer, "}},\"fund_assets\":{{")?;
+            for (j, assets) in log.fund_assets.iter().enumerate() {
+                let sep = if j == 0 { "" } else { "," };
+                write!(writer, "{}{}:{}", sep, names[j], json_number(assets[i]))?;
+            }
+            write!(writer, "}},\"asset\":{}}}", json_number(log.asset[i]))?;
+        }
+        writeln!(writer, "]")?;
+        Ok(())
+    }
+}
+
+/// Columns of the logs of a `TransactionIterator`.
+struct IterLogTable<'a> {
+    dates: &'a [chrono::NaiveDate],
+    cash: Vec<f64>,
+    shares: Vec<Vec<f64>>,
+    fund_assets: Vec<Vec<f64>>,
+    asset: Vec<f64>,
+}
+
+impl<'a> IterLogTable<'a> {
+    fn new(it: &'a TransactionIterator) -> Result<Self, ExportError> {
+        let error = || ExportError("log is not enabled for transaction iterator");
+        let cash = it.cash_log().ok_or_else(error)?.to_vec();
+        let mut shares = Vec::with_capacity(it.nfunds());
+        let mut fund_assets = Vec::with_capacity(it.nfunds());
+        for j in 0..it.nfunds() {
+            shares.push(it.share_log(j).ok_or_else(error)?.to_vec());
+            fund_assets.push(it.fund_asset_log(j).ok_or_else(error)?.to_vec());
+        }
+        let asset = it.asset_log().ok_or_else(error)?.to_vec();
+        Ok(IterLogTable {
+            dates: it.dates(),
+            cash,
+            shares,
+            fund_assets,
+            asset,
+        })
+    }
+}
+
+/// Quote a CSV field if necessary.
+pub(crate) fn csv_field(s: &str) -> Cow<'_, str> {
+    if s.contains([',', '"', '\n', '\r']) {
+        Cow::from(format!("\"{}\"", s.replace('"', "\"\"")))
+    } else {
+        Cow::from(s)
+    }
+}
+
+/// Quote and escape a JSON string.
+pub(crate) fn json_string(s: &str) -> String {
+    let mut res = String::with_capacity(s.len() + 2);
+    res.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => res.push_str("\\\""),
+            '\\' => res.push_str("\\\\"),
+            '\n' => res.push_str("\\n"),
+            '\r' => res.push_str("\\r"),
+            '\t' => res.push_str("\\t"),
+            c if (c as u32) < 0x20 => res.push_str(&format!("\\u{:04x}", c as u32)),
+            c => res.push(c),
+        }
+    }
+    res.push('"');
+    res
+}
+
+/// Format a float as JSON number, using `null` for NaN and infinity.
+pub(crate) fn json_number(x: f64) -> String {
+    if x.is_finite() {
+        format!("{}", x)
+    } else {
+        "null".to_string()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::*;
+
+    #[test]
+    fn test_export_record() {
+        let mut record = DetailedRecord::new("hs300", "123456");
+        record.append(
+            NaiveDate::parse_from_str("2024-01-01", "%Y-%m-%d").unwrap(),
+            10.,
+            1.,
+            9.9,
+            "a very long comment which should not be truncated",
+        );
+        let mut buffer = Vec::new();
+        record.write_csv(&mut buffer).unwrap();
+        let csv = String::from_utf8(buffer).unwrap();
+        assert_eq!(
+            csv.lines().nth(1).unwrap(),
+            "2024-01-01,10,1,9.9,0.09999999999999964,10,9.9,9.9,-0.09999999999999964,\
+             a very long comment which should not be truncated"
+        );
+
+        let mut buffer = Vec::new();
+        record.write_json(&mut buffer).unwrap();
+        let json = String::from_utf8(buffer).unwrap();
+        assert!(json.starts_with("{\"name\":\"hs300\",\"code\":\"123456\",\"comment\":\"\""));
+        assert!(json.contains("\"share\":9.9,"));
+        assert!(
+            json.contains("\"comment\":\"a very long comment which should not be truncated\"}]}")
+        );
+    }
+
+    #[test]
+    fn test_export_log() {
+        let hs300 = Fund::from(&read_gta("hs300.txt").unwrap());
+        let gz2000 = Fund::from(&read_gta("gz2000.txt").unwrap());
+        let start_date = NaiveDate::parse_from_str("2024-01-01", "%Y-%m-%d").unwrap();
+        let end_date = NaiveDate::parse_from_str("2024-01-20", "%Y-%m-%d").unwrap();
+        let t = Transaction::new(&[&hs300, &gz2000], Some(start_date), Some(end_date));
+        let mut it = t.iter(true, false);
+        it.inflow(100.).unwrap();
+        it.buy(0, 50., 0.).unwrap();
+        while it.next_day().is_some() {}
+
+        let mut buffer = Vec::new();
+        it.write_csv(&mut buffer).unwrap();
+        let csv = String::from_utf8(buffer).unwrap();
+        let mut lines = csv.lines();
+        assert_eq!(
+            lines.next().unwrap(),
+            format!(
+                "date,cash,share_{0},share_{1},asset_{0},asset_{1},asset",
+                t.names()[0],
+                t.names()[1]
+            )
+        );
+        assert_eq!(lines.count(), it.dates().len());
+
+        let mut buffer = Vec::new();
+        it.write_json(&mut buffer).unwrap();
+        let json = String::from_utf8(buffer).unwrap();
+        assert!(json.starts_with("[{\"date\":\"2024-01-02\",\"cash\":50,\"shares\":{"));
+
+        let it = t.iter(false, false);
+        assert!(it.write_csv(&mut Vec::new()).is_err());
+    }
+}
@@ -0,0 +1,392 @@
+use std::error::Error;
+use std::fs;
+
+use chrono::NaiveDate;
+use encoding_rs::Encoding;
+
+use crate::DetailedRecord;
+
+#[derive(Debug)]
+pub struct ImportError(String);
+
+impl std::fmt::Display for ImportError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        writeln!(f, "Import Error: {}", self.0)
+    }
+}
+
+impl std::error::Error for ImportError {}
+
+/// Type of a transaction in a statement.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StatementType {
+    Subscribe,
+    Redeem,
+    Dividend,
+}
+
+/// Column mapping of a transaction statement exported by fund
+/// platforms.
+///
+/// Columns are identified by their names in the header line. The
+/// default mapping follows the common Chinese headers, and each
+/// field can be changed to match a specific platform:
+///
+/// ```
+/// use eatmud::import::StatementFormat;
+/// let format = StatementFormat {
+///     encoding: encoding_rs::GBK,
+///     date: "成交日期".to_string(),
+///     ..Default::default()
+/// };
+/// ```
+#[derive(Debug, Clone)]
+pub struct StatementFormat {
+    /// Encoding of the file. A UTF-8 byte order mark is always
+    /// recognized.
+    pub encoding: &'static Encoding,
+    /// Field delimiter, usually ',' or '\t'.
+    pub delimiter: char,
+    /// Format of the dates, see `chrono::format::strftime`.
+    pub date_format: String,
+    /// Confirmation date.
+    pub date: String,
+    /// Fund code.
+    pub code: String,
+    /// Fund name, which is optional in the statement.
+    pub name: Option<String>,
+    /// Amount of money. For subscriptions it is the total payment
+    /// including fee, and for cash dividends it is the dividend.
+    pub amount: String,
+    /// Confirmed NAV.
+    pub nav: String,
+    /// Confirmed shares.
+    pub share: String,
+    /// Fee, which is optional in the statement.
+    pub fee: Option<String>,
+    /// Type of the transaction.
+    pub kind: String,
+    /// Keywords of `kind` for subscriptions.
+    pub subscribe: Vec<String>,
+    /// Keywords of `kind` for redemptions.
+    pub redeem: Vec<String>,
+    /// Keywords of `kind` for dividends.
+    pub dividend: Vec<String>,
+}
+
+impl Default for StatementFormat {
+    fn default() -> Self {
+        let strings = |v: &[&str]| v.iter().map(|s| s.to_string()).collect();
+        StatementFormat {
+            encoding: encoding_rs::UTF_8,
+            delimiter: ',',
+            date_format: "%Y-%m-%d".to_string(),
+            date: "确认日期".to_string(),
+            code: "基金代码".to_string(),
+            name: Some("基金名称".to_string()),
+            amount: "确认金额".to_string(),
+            nav: "确认净值".to_string(),
+            share: "确认份额".to_string(),
+            fee: Some("手续费".to_string()),
+            kind: "业务类型".to_string(),
+            subscribe: strings(&["申购", "认购", "定投", "买入"]),
+            redeem: strings(&["赎回", "卖出"]),
+            dividend: strings(&["分红", "红利"]),
+        }
+    }
+}
+
+impl StatementFormat {
+    fn statement_type(&self, kind: &str) -> Option<StatementType> {
+        let matches = |keys: &[String]| keys.iter().any(|k| kind.contains(k.as_str()));
+        // Check dividends first, as "红利再投资" may contain keywords
+        // of subscriptions.
+        if matches(&self.dividend) {
+            Some(StatementType::Dividend)
+        } else if matches(&self.redeem) {
+            Some(StatementType::Redeem)
+        } else if matches(&self.subscribe) {
+            Some(StatementType::Subscribe)
+        } else {
+            None
+        }
+    }
+}
+
+/// Read a transaction statement file into one record for each fund.
+///
+/// See `parse_statement` for details.
+pub fn read_statement(
+    path: &str,
+    format: &StatementFormat,
+) -> Result<Vec<DetailedRecord>, Box<dyn Error>> {
+    let bytes = fs::read(path)?;
+    parse_statement(&bytes, format)
+}
+
+/// Parse a transaction statement into one record for each fund.
+///
+/// Records are ordered by the first appearance of the funds in the
+/// statement and the transactions of each fund are sorted by date.
+/// Each transaction is converted as follows:
+///
+///  -------------- | ------------------------- | ---------- | -------
+///  type           | investment                | share      | fee
+///  -------------- | ------------------------- | ---------- | -------
+///  subscribe      | `amount`                  | `share`    | `fee`
+///  redeem         | -(`share` * `nav` - `fee`)| -`share`   | `fee`
+///  cash dividend  | -`amount`                 | 0          | 0
+///  reinvestment   | 0                         | `share`    | 0
+///  -------------- | ------------------------- | ---------- | -------
+///
+/// A dividend is taken as reinvested if it has a positive `share`.
+/// Without the fee column, the fee of a subscription is derived by
+/// `amount - nav * share`, and that of a redemption is 0.
+/// Signs of the values in the statement are ignored. Lines whose
+/// type is not recognized are skipped.
+pub fn parse_statement(
+    bytes: &[u8],
+    format: &StatementFormat,
+) -> Result<Vec<DetailedRecord>, Box<dyn Error>> {
+    let (text, _encoding, lossy) = format.encoding.decode(bytes);
+    if lossy {
+        return Err(Box::new(ImportError(format!(
+            "statement is not valid {}",
+            format.encoding.name()
+        ))));
+    }
+    let mut lines = text.lines().filter(|l| !l.trim().is_empty());
+    let Some(header) = lines.next() else {
+        return Err(Box::new(ImportError("empty statement".to_string())));
+    };
+    let header = split_line(header, format.delimiter);
+    let column = |name: &str| {
+        header
+            .iter()
+            .position(|h| h == name)
+            .ok_or_else(|| ImportError(format!("column `{}` not found", name)))
+    };
+    let date_col = column(&format.date)?;
+    let code_col = column(&format.code)?;
+    let name_col = format.name.as_deref().map(column).transpose()?;
+    let amount_col = column(&format.amount)?;
+    let nav_col = column(&format.nav)?;
+    let share_col = column(&format.share)?;
+    let fee_col = format.fee.as_deref().map(column).transpose()?;
+    let kind_col = column(&format.kind)?;
+
+    let mut transactions = Vec::new();
+    for (lineno, line) in lines.enumerate() {
+        let fields = split_line(line, format.delimiter);
+        let field = |i: usize| fields.get(i).map(|s| s.as_str()).unwrap_or("");
+        let Some(kind) = format.statement_type(field(kind_col)) else {
+            continue;
+        };
+        let error = |name: &str| ImportError(format!("invalid {} in line {}", name, lineno + 2));
+        let date = NaiveDate::parse_from_str(field(date_col), &format.date_format)
+            .map_err(|_| error("date"))?;
+        let number = |i: usize, name: &str| {
+            parse_number(field(i))
+                .map(f64::abs)
+                .ok_or_else(|| error(name))
+        };
+        let amount = number(amount_col, "amount")?;
+        let nav = number(nav_col, "nav")?;
+        let share = number(share_col, "share")?;
+        let fee = fee_col.map(|i| number(i, "fee")).transpose()?;
+        let (investment, share, fee) = match kind {
+            StatementType::Subscribe => (amount, share, fee.unwrap_or(amount - nav * share)),
+            StatementType::Redeem => {
+                let fee = fee.unwrap_or(0.);
+                (-(share * nav - fee), -share, fee)
+            }
+            StatementType::Dividend if share > 0. => (0., share, 0.),
+            StatementType::Dividend => (-amount, 0., 0.),
+        };
+        transactions.push(StatementEntry {
+            date,
+            code: field(code_col).to_string(),
+            name: name_col.map(|i| field(i).to_string()).unwrap_or_default(),
+            investment,
+            nav,
+            share,
+            fee,
+            comment: field(kind_col).to_string(),
+        });
+    }
+
+    let mut codes: Vec<&str> = Vec::new();
+    for t in &transactions {
+        if !codes.contains(&t.code.as_str()) {
+            codes.push(&t.code);
+        }
+    }
+    let mut records = Vec::with_capacity(codes.len());
+    for code in codes {
+        let mut items: Vec<_> = transactions.iter().filter(|t| t.code == code).collect();
+        items.sort_by_key(|t| t.date);
+        let mut record = DetailedRecord::new(&items[0].name, code);
+        for t in items {
+            record.append_fee(t.date, t.investment, t.nav, t.share, t.fee, &t.comment);
+        }
+        records.push(record);
+    }
+    Ok(records)
+}
+
+/// One transaction parsed from the statement.
+struct StatementEntry {
+    date: NaiveDate,
+    code: String,
+    name: String,
+    investment: f64,
+    nav: f64,
+    share: f64,
+    fee: f64,
+    comment: String,
+}
+
+/// Split a line of CSV, taking quotes into account.
+fn split_line(line: &str, delimiter: char) -> Vec<String> {
+    let mut fields = Vec::new();
+    let mut field = String::new();
+    let mut quoted = false;
+    let mut chars = line.chars().peekable();
+    while let Some(c) = chars.next() {
+        if quoted {
+            if c == '"' {
+                if chars.peek() == Some(&'"') {
+                    field.push('"');
+                    chars.next();
+                } else {
+                    quoted = false;
+                }
+            } else {
+                field.push(c);
+            }
+        } else if c == '"' {
+            quoted = true;
+        } else if c == delimiter {
+            fields.push(field.trim().to_string());
+            field.clear();
+        } else {
+            field.push(c);
+        }
+    }
+    fields.push(field.trim().to_string());
+    fields
+}
+
+/// Parse a number, ignoring thousands separators and currency signs.
+/// Empty fields and `--` are taken as zero.
+fn parse_number(s: &str) -> Option<f64> {
+    let s: String = s
+        .chars()
+        .filter(|c| !matches!(c, ',' | '¥' | '￥' | '元') && !c.is_whitespace())
+        .collect();
+    if s.is_empty() || s == "--" {
+        Some(0.)
+    } else {
+        s.parse().ok()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::*;
+
+    #[test]
+    fn test_parse_statement() {
+        let text = "确认日期,基金代码,基金名称,业务类型,确认金额,确认净值,确认份额,手续费\n\
+                    2024-01-03,000300,沪深300,申购,\"1,000.00\",1.0000,998.50,1.50\n\
+                    2024-01-02,000300,沪深300,定投,100.00,1.0000,100.00,0.00\n\
+                    2024-01-05,399303,国证2000,申购,500.00,2.0000,250.00,0.00\n\
+                    2024-01-10,000300,沪深300,现金分红,10.00,1.1000,0,0\n\
+                    2024-01-11,000300,沪深300,红利再投资,0,1.1000,10.00,0\n\
+                    2024-01-12,000300,沪深300,赎回,549.25,1.1000,-500.00,0.75\n\
+                    2024-01-15,000300,沪深300,撤单,0,0,0,0\n";
+        let (bytes, _, _) = encoding_rs::GBK.encode(text);
+        let format = StatementFormat {
+            encoding: encoding_rs::GBK,
+            ..Default::default()
+        };
+        let records = parse_statement(&bytes, &format).unwrap();
+        assert_eq!(records.len(), 2);
+        let hs300 = &records[0];
+        assert_eq!(hs300.name(), "沪深300");
+        assert_eq!(hs300.code(), "000300");
+        assert_eq!(hs300.len(), 5);
+        assert_eq!(
+            hs300[0].date(),
+            NaiveDate::from_ymd_opt(2024, 1, 2).unwrap()
+        );
+        assert_eq!(hs300[1].investment(), 1000.);
+        assert!((hs300[1].fee() - 1.5).abs() < 1e-9);
+        assert_eq!(hs300[2].investment(), -10.);
+        assert_eq!(hs300[2].fee(), 0.);
+        assert_eq!(hs300[3].investment(), 0.);
+        assert_eq!(hs300[3].share(), 10.);
+        assert_eq!(hs300[3].fee(), 0.);
+        assert!((hs300[4].investment() + 549.25).abs() < 1e-9);
+        assert!((hs300[4].fee() - 0.75).abs() < 1e-9);
+        assert!((hs300[4].total_share() - 608.5).abs() < 1e-9);
+        assert_eq!(records[1].records()[0].share(), 250.);
+
+        let utf8 = format!("\u{feff}{}", text);
+        let records = parse_statement(utf8.as_bytes(), &format).unwrap();
+        assert_eq!(records[0].name(), "沪深300");
+        let format = StatementFormat {
+            date: "日期".to_string(),
+            ..Default::default()
+        };
+        assert!(parse_statement(utf8.as_bytes(), &format).is_err());
+    }
+
+    #[test]
+    fn test_parse_number() {
+        assert_eq!(parse_number("1e-3"), Some(1e-3));
+        assert_eq!(parse_number("-1,000.50"), Some(-1000.5));
+        assert_eq!(parse_number("¥+12 元"), Some(12.));
+        assert_eq!(parse_number("--"), Some(0.));
+        assert_eq!(parse_number(""), Some(0.));
+        assert_eq!(parse_number("1-2"), None);
+
+        // GBK text is not valid UTF-8.
+        let (bytes, _, _) = encoding_rs::GBK.encode("日期\n");
+        let err = parse_statement(&bytes, &StatementFormat::default()).unwrap_err();
+        assert!(err.to_string().contains("not valid UTF-8"));
+    }
+
+    #[test]
+    fn test_statement_revalue() {
+        let hs300 = Fund::from(&read_gta("hs300.txt").unwrap());
+        let text = "date\tcode\ttype\tamount\tnav\tshare\n\
+                    2023/01/03\t000300\tbuy\t3887.89\t3887.89\t1\n\
+                    2023/06/01\t000300\tsell\t0\t3784.00\t1\n\
+                    2023/07/03\t000300\tbuy\t3906.05\t3906.05\t1\n";
+        let format = StatementFormat {
+            delimiter: '\t',
+            date_format: "%Y/%m/%d".to_string(),
+            date: "date".to_string(),
+            code: "code".to_string(),
+            name: None,
+            amount: "amount".to_string(),
+            nav: "nav".to_string(),
+            share: "share".to_string(),
+            fee: None,
+            kind: "type".to_string(),
+            subscribe: vec!["buy".to_string()],
+            redeem: vec!["sell".to_string()],
+            ..Default::default()
+        };
+        let mut records = parse_statement(text.as_bytes(), &format).unwrap();
+        let record = &mut records[0];
+        record.revalue(&hs300);
+        assert_eq!(record.len(), 4);
+        let last = &record[3];
+        assert_eq!(last.date(), hs300[hs300.len() - 1].date());
+        assert!((last.present_value() - hs300[hs300.len() - 1].value()).abs() < 1e-9);
+        assert!(record.irr_naive() < 0.);
+    }
+}
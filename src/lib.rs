@@ -1,15 +1,23 @@
 mod common;
+pub mod analysis;
 pub mod data;
+pub mod export;
+pub mod import;
+pub mod portfolio;
 pub mod prelude;
 pub mod record;
+pub mod report;
+pub mod simulation;
 pub mod transaction;
 pub mod utility;
 pub mod strategy;
 
 pub use chrono::{Duration, NaiveDate};
 pub use data::{read_gta, Fund, Stock};
+pub use export::Export;
 pub use ndarray::{Array1, Array2, ArrayView1, ArrayView2};
+pub use portfolio::Portfolio;
 pub use prelude::*;
 pub use record::{ConciseRecord, DetailedRecord};
-pub use transaction::{Transaction, TransactionIterator, Weekday};
+pub use transaction::{Schedule, Snapshot, Transaction, TransactionIterator, Weekday};
 pub use utility::{SIDE, DAYS_PER_YEAR};
@@ -0,0 +1,289 @@
+use std::error::Error;
+
+use chrono::NaiveDate;
+use ndarray::Array1;
+
+use crate::record::{merge, RecordLike};
+use crate::{ConciseRecord, Schedule, Transaction, TransactionIterator, Weekday};
+
+#[derive(Debug)]
+pub struct PortfolioError(&'static str);
+
+impl std::fmt::Display for PortfolioError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        writeln!(f, "PortfolioError: {}", self.0)
+    }
+}
+
+impl std::error::Error for PortfolioError {}
+
+/// Strategy of one account, which is called on each scheduled date.
+pub type AccountStrategy<'s, 'a> =
+    &'s mut dyn FnMut(&mut TransactionIterator<'a>) -> Result<(), Box<dyn Error>>;
+
+/// Several accounts transacting over the same `Transaction` object.
+///
+/// Each account is a `TransactionIterator` with its own cash and
+/// shares, such as a taxable account and a pension account of one
+/// household. The accounts are stepped together, so that they always
+/// stay at the same date. Accounts should not be stepped individually
+/// by `account_mut`.
+pub struct Portfolio<'a> {
+    names: Vec<String>,
+    accounts: Vec<TransactionIterator<'a>>,
+}
+
+impl<'a> Portfolio<'a> {
+    /// Create a portfolio of accounts with the given names.
+    ///
+    /// # Examples
+    /// ```
+    /// use eatmud::{read_gta, Fund, Portfolio, Transaction};
+    /// let hs300 = Fund::from(&read_gta("hs300.txt").unwrap());
+    /// let t = Transaction::new(&[&hs300], None, None);
+    /// let mut p = Portfolio::new(&t, &["taxable", "pension"], false, true);
+    /// p.account_mut(0).inflow(100.).unwrap();
+    /// p.transfer(0, 1, 40.).unwrap();
+    /// p.next_day();
+    /// assert_eq!(p.account(1).cash(), 40.);
+    /// assert_eq!(p.asset(), 100.);
+    /// ```
+    ///
+    /// # Panics
+    ///
+    /// Panics if `names` is empty.
+    pub fn new(trans: &'a Transaction, names: &[&str], save_log: bool, save_record: bool) -> Self {
+        assert!(
+            !names.is_empty(),
+            "portfolio should have at least one account"
+        );
+        Portfolio {
+            names: names.iter().map(|s| s.to_string()).collect(),
+            accounts: names
+                .iter()
+                .map(|_| trans.iter(save_log, save_record))
+                .collect(),
+        }
+    }
+
+    /// The `Transaction` object shared by the accounts.
+    pub fn transaction(&self) -> &'a Transaction {
+        self.accounts[0].transaction()
+    }
+
+    pub fn naccounts(&self) -> usize {
+        self.accounts.len()
+    }
+
+    pub fn names(&self) -> &[String] {
+        &self.names
+    }
+
+    /// Index of the account with the given name.
+    pub fn position(&self, name: &str) -> Option<usize> {
+        self.names.iter().position(|n| n == name)
+    }
+
+    pub fn account(&self, idx: usize) -> &TransactionIterator<'a> {
+        &self.accounts[idx]
+    }
+
+    pub fn account_mut(&mut self, idx: usize) -> &mut TransactionIterator<'a> {
+        &mut self.accounts[idx]
+    }
+
+    pub fn today(&self) -> NaiveDate {
+        self.accounts[0].today()
+    }
+
+    /// Sequence of dates have iterated.
+    pub fn dates(&self) -> &[NaiveDate] {
+        self.accounts[0].dates()
+    }
+
+    /// Total asset of all the accounts at the *beginning* of the day.
+    pub fn asset(&self) -> f64 {
+        self.accounts.iter().map(|it| it.asset()).sum()
+    }
+
+    /// Log of total asset of all the accounts.
+    pub fn asset_log(&self) -> Option<Array1<f64>> {
+        let mut log = self.accounts[0].asset_log()?;
+        for it in &self.accounts[1..] {
+            log += &it.asset_log()?;
+        }
+        Some(log)
+    }
+
+    /// Transfer cash from one account to another.
+    ///
+    /// The transfer is settled at the end of the day like `inflow`,
+    /// and it is recorded as an outflow of account `from` and an
+    /// inflow of account `to`.
+    pub fn transfer(
+        &mut self,
+        from: usize,
+        to: usize,
+        amount: f64,
+    ) -> Result<&mut Self, Box<dyn Error>> {
+        if from == to {
+            return Err(Box::new(PortfolioError(
+                "can not transfer within the same account",
+            )));
+        }
+        if amount < 0. {
+            return Err(Box::new(PortfolioError(
+                "amount of transfer should be non-negative",
+            )));
+        }
+        let comment = format!("transfer to {}", self.names[to]);
+        self.accounts[from].inflow_comment(-amount, &comment)?;
+        let comment = format!("transfer from {}", self.names[from]);
+        self.accounts[to].inflow_comment(amount, &comment)?;
+        Ok(self)
+    }
+
+    /// Step the first account by `f`, and the others to the same date.
+    fn step_with<F>(&mut self, f: F) -> Option<&mut Self>
+    where
+        F: FnOnce(&mut TransactionIterator<'a>) -> Option<()>,
+    {
+        let (first, rest) = self.accounts.split_first_mut().unwrap();
+        let stepped = f(first);
+        let date = match stepped {
+            Some(()) => first.today(),
+            None => first.transaction().end_date(),
+        };
+        for it in rest {
+            it.goto(date);
+        }
+        stepped.map(|_| self)
+    }
+
+    pub fn next_day(&mut self) -> Option<&mut Self> {
+        self.step_with(|it| it.next_day().map(|_| ()))
+    }
+
+    /// Step all the accounts like `TransactionIterator::next_weekday`.
+    pub fn next_weekday(&mut self, weekday: Option<Weekday>) -> Option<&mut Self> {
+        self.step_with(|it| it.next_weekday(weekday).map(|_| ()))
+    }
+
+    /// Step all the accounts like `TransactionIterator::goto`.
+    pub fn goto(&mut self, date: NaiveDate) -> Option<&mut Self> {
+        self.step_with(|it| it.goto(date).map(|_| ()))
+    }
+
+    /// Step all the accounts like `TransactionIterator::next_month`.
+    pub fn next_month(&mut self, day: Option<u32>) -> Option<&mut Self> {
+        self.step_with(|it| it.next_month(day).map(|_| ()))
+    }
+
+    /// Step all the accounts to the next date of `schedule`.
+    pub fn next_schedule(&mut self, schedule: Schedule) -> Option<&mut Self> {
+        self.step_with(|it| it.next_schedule(schedule).map(|_| ()))
+    }
+
+    /// Run a strategy for each account until the end of iteration.
+    ///
+    /// `strategies[i]` is called for account `i` today and on each
+    /// following date of `schedule`. Transfers between accounts can be
+    /// made by stepping the portfolio manually instead.
+    pub fn run(
+        &mut self,
+        schedule: Schedule,
+        strategies: &mut [AccountStrategy<'_, 'a>],
+    ) -> Result<(), Box<dyn Error>> {
+        if strategies.len() != self.naccounts() {
+            return Err(Box::new(PortfolioError(
+                "number of strategies should match number of accounts",
+            )));
+        }
+        loop {
+            for (it, f) in self.accounts.iter_mut().zip(strategies.iter_mut()) {
+                f(it)?;
+            }
+            if self.next_schedule(schedule).is_none() {
+                return Ok(());
+            }
+        }
+    }
+
+    /// Record of each account, named by the account.
+    pub fn account_record(&self, idx: usize) -> Option<ConciseRecord> {
+        let mut record = self.accounts[idx].record()?;
+        record.name = self.names[idx].clone();
+        Some(record)
+    }
+
+    /// Consolidated record of all the accounts.
+    ///
+    /// Comments are labelled by the names of the accounts. Transfers
+    /// between accounts cancel out, so that the record describes the
+    /// household as a whole.
+    pub fn record(&self) -> Option<ConciseRecord> {
+        let records = (0..self.naccounts())
+            .map(|i| self.account_record(i))
+            .collect::<Option<Vec<_>>>()?;
+        let refs: Vec<&dyn RecordLike> = records.iter().map(|r| r as &dyn RecordLike).collect();
+        let mut res = merge(&refs, true);
+        res.name = "Consolidated Record".to_string();
+        Some(res)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::*;
+
+    #[test]
+    fn test_portfolio() {
+        let hs300 = Fund::from(&read_gta("hs300.txt").unwrap());
+        let gz2000 = Fund::from(&read_gta("gz2000.txt").unwrap());
+        let start_date = NaiveDate::parse_from_str("20200101", "%Y%m%d").unwrap();
+        let end_date = NaiveDate::parse_from_str("20230101", "%Y%m%d").unwrap();
+        let t = Transaction::new(&[&hs300, &gz2000], Some(start_date), Some(end_date));
+        let mut p = Portfolio::new(&t, &["taxable", "pension", "education"], true, true);
+        p.account_mut(0).inflow_comment(1000., "salary").unwrap();
+        p.transfer(0, 2, 300.).unwrap();
+        assert!(p.transfer(1, 1, 10.).is_err());
+
+        p.run(
+            Schedule::Monthly(1),
+            &mut [
+                &mut |it| {
+                    it.inflow(100.)?.buy(0, 100., 0.)?;
+                    Ok(())
+                },
+                &mut |it| {
+                    it.inflow(50.)?.buy(1, 50., 0.)?;
+                    Ok(())
+                },
+                &mut |_| Ok(()),
+            ],
+        )
+        .unwrap();
+        assert!(p.accounts.iter().all(|it| it.dates().len() == t.ndays()));
+        assert_eq!(p.account(0).cash(), 700.);
+        assert_eq!(p.account(2).cash(), 300.);
+        let asset = p.asset();
+        assert!((p.asset_log().unwrap()[t.ndays() - 1] - asset).abs() < 1e-9);
+
+        let n = Schedule::Monthly(1).select(t.date()).len() as f64;
+        let record = p.record().unwrap();
+        let last = &record[record.len() - 1];
+        assert!((last.total_investment() - (1000. + 150. * n)).abs() < 1e-9);
+        let present_value: f64 = (0..p.naccounts())
+            .map(|i| {
+                let r = p.account_record(i).unwrap();
+                r[r.len() - 1].present_value()
+            })
+            .sum();
+        assert!((last.present_value() - present_value).abs() < 1e-6);
+        assert!(record[0]
+            .comment()
+            .contains("education: transfer from taxable"));
+        assert_eq!(p.position("pension"), Some(1));
+    }
+}
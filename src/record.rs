@@ -2,6 +2,7 @@ use chrono::NaiveDate;
 use core::fmt;
 use std::ops::Index;
 
+use crate::data::{DataSlice, Fund};
 use crate::utility::{irr, search_sorted};
 
 pub trait RecordSlice {
@@ -213,13 +214,13 @@ impl<Rs: RecordSlice> Record<Rs> {
     /// # Arguments
     ///
     /// * `start_date` - The start date for calculating IRR.
-    /// Defaults to the first date in the records.
+    ///   Defaults to the first date in the records.
     /// * `end_date` - The end date for calculating IRR.
-    /// Defaults to the last date in the records.
+    ///   Defaults to the last date in the records.
     /// * `start_value` - Value at the start date.
-    /// If None is given, it will be evaluated from the nearest date in the records.
+    ///   If None is given, it will be evaluated from the nearest date in the records.
     /// * `end_value` - Value at the end date.
-    /// If None is given, it will be evaluated from the nearest date in the records.
+    ///   If None is given, it will be evaluated from the nearest date in the records.
     /// * `x0` - The initial value for iteration. Default to 0.0.
     pub fn irr(
         &self,
@@ -329,6 +330,30 @@ impl Record<DetailedRecordSlice> {
         nav: f64,
         share: f64,
         comment: &str,
+    ) {
+        self.append_fee(
+            date,
+            investment,
+            nav,
+            share,
+            investment - nav * share,
+            comment,
+        );
+    }
+
+    /// Appends new data with the given fee, instead of the fee derived
+    /// by `investment - nav * share` as `append` does.
+    ///
+    /// It is useful for transactions whose investment is not paid for
+    /// the shares, such as dividends.
+    pub fn append_fee(
+        &mut self,
+        date: NaiveDate,
+        investment: f64,
+        nav: f64,
+        share: f64,
+        fee: f64,
+        comment: &str,
     ) {
         if !self.is_empty() && date < self[self.len() - 1].date() {
             panic!("date must be ordered");
@@ -346,13 +371,135 @@ impl Record<DetailedRecordSlice> {
             nav,
             share,
             comment: comment.to_string(),
-            fee: investment - nav * share,
+            fee,
             total_investment,
             total_share,
             present_value,
             profit: present_value - total_investment,
         })
     }
+
+    /// Revalue the present values of the record by a price series.
+    ///
+    /// The present value of each slice is evaluated by the total
+    /// share and the price of `fund` at the date of the slice (or
+    /// the nearest date before it), while the confirmed NAV of each
+    /// transaction is kept. Slices before the beginning of `fund`
+    /// are not changed. If `fund` ends after the last slice, a slice
+    /// without investment is appended at the last date of `fund`, so
+    /// that the IRR is evaluated up to then.
+    ///
+    /// # Examples
+    /// ```
+    /// use chrono::NaiveDate;
+    /// use eatmud::{prelude::*, DetailedRecord, Fund};
+    /// let date = |s| NaiveDate::parse_from_str(s, "%Y-%m-%d").unwrap();
+    /// let mut fund = Fund::new("hs300", "000300");
+    /// fund.append(date("2024-01-01"), 1.0);
+    /// fund.append(date("2024-01-02"), 1.1);
+    /// fund.append(date("2024-01-03"), 1.2);
+    /// let mut record = DetailedRecord::new("hs300", "000300");
+    /// record.append(date("2024-01-02"), 10., 1.0, 10., "subscribe");
+    /// record.revalue(&fund);
+    /// assert_eq!(record.len(), 2);
+    /// assert!((record[0].present_value() - 11.).abs() < 1e-9);
+    /// assert!((record[1].present_value() - 12.).abs() < 1e-9);
+    /// ```
+    pub fn revalue(&mut self, fund: &Fund) {
+        if fund.is_empty() {
+            return;
+        }
+        for rs in self.records.iter_mut() {
+            let idx = fund.data().partition_point(|s| s.date() <= rs.date);
+            if idx == 0 {
+                continue;
+            }
+            rs.present_value = fund[idx - 1].value() * rs.total_share;
+            rs.profit = rs.present_value - rs.total_investment;
+        }
+        let last = &fund[fund.len() - 1];
+        if !self.is_empty() && self[self.len() - 1].date() < last.date() {
+            self.append(last.date(), 0., last.value(), 0., "");
+        }
+    }
+
+    /// Mark the record to market daily by a price series.
+    ///
+    /// Returns a record which has one slice for each date of `fund`
+    /// since the first slice of this record. Days without
+    /// transactions have zero investment, and the present value of
+    /// each day is evaluated by the total share and the price of
+    /// `fund` of that day. Transactions on dates not in `fund` are
+    /// merged into the following date of `fund`, and transactions
+    /// after the last date of `fund` are kept as slices of their own
+    /// dates, valued at the last price of `fund` like `revalue` does.
+    ///
+    /// # Examples
+    /// ```
+    /// use chrono::NaiveDate;
+    /// use eatmud::{prelude::*, DetailedRecord, Fund};
+    /// let date = |s| NaiveDate::parse_from_str(s, "%Y-%m-%d").unwrap();
+    /// let mut fund = Fund::new("hs300", "000300");
+    /// fund.append(date("2024-01-01"), 1.0);
+    /// fund.append(date("2024-01-02"), 1.1);
+    /// fund.append(date("2024-01-03"), 1.2);
+    /// fund.append(date("2024-01-04"), 0.9);
+    /// let mut record = DetailedRecord::new("hs300", "000300");
+    /// record.append(date("2024-01-02"), 11., 1.1, 10., "subscribe");
+    /// let daily = record.mark_to_market(&fund);
+    /// assert_eq!(daily.len(), 3);
+    /// assert_eq!(daily[1].investment(), 0.);
+    /// assert!((daily[1].present_value() - 12.).abs() < 1e-9);
+    /// assert!((daily[2].profit() + 2.).abs() < 1e-9);
+    /// ```
+    pub fn mark_to_market(&self, fund: &Fund) -> ConciseRecord {
+        let mut record = ConciseRecord::new_comment(self.name(), self.code(), self.comment());
+        if self.is_empty() {
+            return record;
+        }
+        let beg = search_sorted(fund.data(), &self[0].date(), |s| s.date(), None);
+        let mut k = 0;
+        let mut total_share = 0.;
+        for fs in &fund.data()[beg..] {
+            let mut investment = 0.;
+            let mut comments = Vec::new();
+            while k < self.len() && self[k].date() <= fs.date() {
+                investment += self[k].investment();
+                total_share = self[k].total_share();
+                if !self[k].comment().is_empty() {
+                    comments.push(self[k].comment());
+                }
+                k += 1;
+            }
+            record.append(
+                fs.date(),
+                investment,
+                total_share * fs.value(),
+                &comments.join("; "),
+            );
+        }
+        for rs in &self.records[k..] {
+            let present_value = match fund.data().last() {
+                Some(last) => rs.total_share() * last.value(),
+                None => rs.present_value(),
+            };
+            record.append(rs.date(), rs.investment(), present_value, rs.comment());
+        }
+        record
+    }
+
+    /// Daily valuation of the record by a price series.
+    ///
+    /// This is the present values of `mark_to_market` as a `Fund`
+    /// object, which can be analysed in the same way as the price
+    /// series.
+    pub fn valuation(&self, fund: &Fund) -> Fund {
+        let mut valuation = Fund::new(self.name(), self.code());
+        for rs in self.mark_to_market(fund).records() {
+            valuation.append(rs.date(), rs.present_value());
+        }
+        valuation
+    }
 }
 
 pub type ConciseRecord = Record<ConciseRecordSlice>;
@@ -451,72 +598,115 @@ impl fmt::Display for DetailedRecord {
     }
 }
 
-#[macro_export]
-macro_rules! merge_records {
-    ($record: expr) => {
-        merge_records!($record, ConciseRecord::new("", ""))
-    };
-    ($record1: expr, $record2: expr) => {{
-        use $crate::record::RecordSlice;
-    let mut record = ConciseRecord::new("", "");
-    let mut it1 = $record1.records().iter();
-    let mut it2 = $record2.records().iter();
-    let mut rs1 = it1.next();
-    let mut rs2 = it2.next();
+/// Object-safe view of a record, used for merging records of
+/// different kinds.
+pub trait RecordLike {
+    fn name(&self) -> &str;
+    fn len(&self) -> usize;
+    fn is_empty(&self) -> bool;
+    fn slice(&self, idx: usize) -> &dyn RecordSlice;
+}
+
+impl<Rs: RecordSlice> RecordLike for Record<Rs> {
+    fn name(&self) -> &str {
+        &self.name
+    }
+    fn len(&self) -> usize {
+        self.records.len()
+    }
+    fn is_empty(&self) -> bool {
+        self.records.is_empty()
+    }
+    fn slice(&self, idx: usize) -> &dyn RecordSlice {
+        &self.records[idx]
+    }
+}
+
+/// Merge any number of records into one.
+///
+/// The merged record has one slice for each date appearing in any of
+/// the records. Its investment is the sum of the investments of all
+/// records on that date, and its present value is the sum of the
+/// latest present values of all records, where a record which has
+/// no slice on that date carries forward its present value from its
+/// last slice (or zero if it has not started yet). Thus the merged
+/// record describes the portfolio of all the records, and its IRR is
+/// the IRR of the portfolio.
+///
+/// # Arguments
+///
+/// * `records` - The records to be merged.
+/// * `label` - Whether to prefix the comments by the names of the
+///   records they come from.
+///
+/// # Examples
+/// ```
+/// use chrono::NaiveDate;
+/// use eatmud::{prelude::*, ConciseRecord, DetailedRecord};
+/// use eatmud::record::merge;
+/// let date = |s| NaiveDate::parse_from_str(s, "%Y-%m-%d").unwrap();
+/// let mut record1 = ConciseRecord::new("cash", "");
+/// record1.append(date("2024-01-01"), 5., 5., "deposit");
+/// let mut record2 = DetailedRecord::new("hs300", "000300");
+/// record2.append(date("2024-01-01"), 10., 1., 10., "buy");
+/// record2.append(date("2024-02-01"), 0., 1.2, 0., "");
+/// let merged = merge(&[&record1, &record2], true);
+/// assert_eq!(merged.len(), 2);
+/// assert_eq!(merged[0].comment(), "cash: deposit; hs300: buy");
+/// assert_eq!(merged[1].present_value(), 17.);
+/// ```
+pub fn merge(records: &[&dyn RecordLike], label: bool) -> ConciseRecord {
+    let mut merged = ConciseRecord::new("", "");
+    let mut indices = vec![0; records.len()];
     // present value cache
-    let mut pv1 = 0.0;
-    let mut pv2 = 0.0;
+    let mut present_values = vec![0.; records.len()];
     loop {
-        let present_date = match (rs1, rs2) {
-            (Some(s1), Some(s2)) => Ord::min(s1.date(), s2.date()),
-            (Some(s1), None) => s1.date(),
-            (None, Some(s2)) => s2.date(),
-            (None, None) => break record,
+        let Some(present_date) = records
+            .iter()
+            .zip(&indices)
+            .filter(|(r, &i)| i < r.len())
+            .map(|(r, &i)| r.slice(i).date())
+            .min()
+        else {
+            break merged;
         };
         let mut present_investment = 0.;
-        let mut comments: Vec<&str> = Vec::new();
-        let mut status1 = false;
-        let mut status2 = false;
-        while let Some(s) = rs1 {
-            if s.date() != present_date {
-                break;
-            }
-            pv1 = s.present_value();
-            present_investment += s.investment();
-            if !s.comment().is_empty() {
-                comments.push(s.comment());
+        let mut comments: Vec<String> = Vec::new();
+        for (k, r) in records.iter().enumerate() {
+            while indices[k] < r.len() && r.slice(indices[k]).date() == present_date {
+                let s = r.slice(indices[k]);
+                present_values[k] = s.present_value();
+                present_investment += s.investment();
+                if !s.comment().is_empty() {
+                    if label {
+                        comments.push(format!("{}: {}", r.name(), s.comment()));
+                    } else {
+                        comments.push(s.comment().to_string());
+                    }
+                }
+                indices[k] += 1;
             }
-            status1 = true;
-            rs1 = it1.next();
         }
-        while let Some(s) = rs2 {
-            if s.date() != present_date {
-                break;
-            }
-            pv2 = s.present_value();
-            present_investment += s.investment();
-            if !s.comment().is_empty() {
-                comments.push(s.comment());
-            }
-            status2 = true;
-            rs2 = it2.next();
-        }
-        if !(status1 && status2) {
-            comments.push("estimated");
-        }
-        record.append(
+        merged.append(
             present_date,
             present_investment,
-            pv1 + pv2,
+            present_values.iter().sum(),
             &comments.join("; "),
         );
-    }}
-    };
-    ($r1: expr, $r2: expr, $($rs: expr), +) => {
-        merge_records!(merge_records!(r1, r2), $($rs), +)
     }
 }
 
+/// Merge records into one `ConciseRecord`.
+///
+/// This is a shortcut for `merge` without labelling comments. See
+/// `merge` for details.
+#[macro_export]
+macro_rules! merge_records {
+    ($($record: expr),+ $(,)?) => {
+        $crate::record::merge(&[$($record as &dyn $crate::record::RecordLike),+], false)
+    };
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
@@ -592,8 +782,135 @@ mod test {
         for i in 1..4 {
             assert_eq!(
                 merged.records()[i].comment(),
-                format!("investment {}; estimated", i + 1)
+                format!("investment {}", i + 1)
             );
         }
+        assert_eq!(merged.records()[1].present_value(), 40.);
+        assert_eq!(merged.records()[2].present_value(), 55.);
+        assert_eq!(merged.records()[3].present_value(), 60.);
+
+        let mut record3 = ConciseRecord::new("bond", "");
+        record3.append(
+            NaiveDate::parse_from_str("2024-01-03", "%Y-%m-%d").unwrap(),
+            1.,
+            1.,
+            "investment 5",
+        );
+        let merged = merge_records!(&record1, &record2, &record3);
+        assert_eq!(merged.len(), 5);
+        assert_eq!(merged.records()[1].comment(), "investment 5");
+        assert_eq!(merged.records()[1].present_value(), 21.);
+        assert_eq!(merged.records()[4].present_value(), 61.);
+        let merged = merge(&[&record3, &record2], true);
+        assert_eq!(merged.records()[0].comment(), "hs300: investment 1");
+    }
+
+    #[test]
+    fn test_merge_irr() {
+        let mut cash = ConciseRecord::new("cash", "");
+        cash.append(
+            NaiveDate::parse_from_str("2024-01-01", "%Y-%m-%d").unwrap(),
+            0.,
+            0.,
+            "",
+        );
+        let mut fund = DetailedRecord::new("hs300", "123456");
+        fund.append(
+            NaiveDate::parse_from_str("2024-01-01", "%Y-%m-%d").unwrap(),
+            100.,
+            1.,
+            100.,
+            "",
+        );
+        fund.append(
+            NaiveDate::parse_from_str("2024-07-01", "%Y-%m-%d").unwrap(),
+            0.,
+            1.05,
+            0.,
+            "",
+        );
+        fund.append(
+            NaiveDate::parse_from_str("2025-01-01", "%Y-%m-%d").unwrap(),
+            0.,
+            1.1,
+            0.,
+            "",
+        );
+        let merged = merge(&[&cash, &fund], false);
+        assert!((merged.irr_naive() - fund.irr_naive()).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_revalue_last_date() {
+        let date = |s| NaiveDate::parse_from_str(s, "%Y-%m-%d").unwrap();
+        let mut fund = Fund::new("hs300", "000300");
+        for (d, nav) in [
+            ("2024-01-01", 1.0),
+            ("2024-01-02", 1.1),
+            ("2024-01-03", 1.2),
+            ("2024-01-04", 2.0),
+        ] {
+            fund.append(date(d), nav);
+        }
+        let mut record = DetailedRecord::new("hs300", "000300");
+        record.append(date("2024-01-01"), 10., 1.0, 10., "");
+        record.append(date("2024-01-04"), 20., 2.0, 10., "");
+        record.revalue(&fund);
+        assert_eq!(record.len(), 2);
+        assert!((record[0].present_value() - 10.).abs() < 1e-9);
+        assert!((record[1].present_value() - 40.).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_mark_to_market() {
+        use crate::{read_gta, utility::drawdown};
+        let hs300 = Fund::from(&read_gta("hs300.txt").unwrap());
+        let mut record = DetailedRecord::new("hs300", "000300");
+        // 2023-01-07 is Saturday
+        for (date, nav) in [("2023-01-03", 3887.89), ("2023-01-07", 3980.89)] {
+            record.append(
+                NaiveDate::parse_from_str(date, "%Y-%m-%d").unwrap(),
+                nav,
+                nav,
+                1.,
+                "buy",
+            );
+        }
+        let daily = record.mark_to_market(&hs300);
+        assert_eq!(daily[0].date(), record[0].date());
+        assert_eq!(daily[4].comment(), "buy");
+        assert_eq!(daily[4].date().to_string(), "2023-01-09");
+        assert_eq!(daily[3].investment(), 0.);
+        assert_eq!(daily[daily.len() - 1].date(), hs300[hs300.len() - 1].date());
+        assert!((daily[daily.len() - 1].present_value() - 2. * 3303.96).abs() < 1e-6);
+
+        let mut revalued = record.clone();
+        revalued.revalue(&hs300);
+        // The second transaction is shifted by two days.
+        assert!((daily.irr_naive() - revalued.irr_naive()).abs() < 1e-3);
+
+        let valuation = record.valuation(&hs300);
+        assert_eq!(valuation.len(), daily.len());
+        let values: Vec<_> = valuation.data().iter().map(|s| s.value()).collect();
+        assert!(drawdown(&values).iter().any(|&x| x > 0.1));
+    }
+
+    /// Transactions after the end of the price series are kept.
+    #[test]
+    fn test_mark_to_market_last_date() {
+        let date = |s| NaiveDate::parse_from_str(s, "%Y-%m-%d").unwrap();
+        let mut fund = Fund::new("fund", "000001");
+        fund.append(date("2024-01-01"), 1.0);
+        fund.append(date("2024-01-02"), 2.0);
+        let mut record = DetailedRecord::new("fund", "000001");
+        record.append(date("2024-01-01"), 10., 1.0, 10., "buy");
+        record.append(date("2024-01-05"), -10., 2.5, -4., "sell");
+        let daily = record.mark_to_market(&fund);
+        assert_eq!(daily.len(), 3);
+        assert_eq!(daily[2].date(), date("2024-01-05"));
+        assert_eq!(daily[2].investment(), -10.);
+        assert_eq!(daily[2].comment(), "sell");
+        assert!((daily[2].present_value() - 12.).abs() < 1e-9);
+        assert!((daily[2].total_investment() - record[1].total_investment()).abs() < 1e-9);
     }
 }
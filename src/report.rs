@@ -0,0 +1,333 @@
+use std::error::Error;
+use std::fmt::Write as _;
+use std::io::Write;
+
+use chrono::NaiveDate;
+
+use crate::analysis::equity_curve;
+use crate::record::RecordSlice;
+use crate::utility::drawdown;
+use crate::TransactionIterator;
+
+#[derive(Debug)]
+pub struct ReportError(&'static str);
+
+impl std::fmt::Display for ReportError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        writeln!(f, "Report Error: {}", self.0)
+    }
+}
+
+impl std::error::Error for ReportError {}
+
+const WIDTH: f64 = 900.;
+const HEIGHT: f64 = 320.;
+const MARGIN_LEFT: f64 = 70.;
+const MARGIN_RIGHT: f64 = 20.;
+const MARGIN_TOP: f64 = 30.;
+const MARGIN_BOTTOM: f64 = 40.;
+const COLORS: [&str; 8] = [
+    "#1f77b4", "#ff7f0e", "#2ca02c", "#d62728", "#9467bd", "#8c564b", "#e377c2", "#7f7f7f",
+];
+
+/// Write a self-contained HTML report of a finished backtest.
+///
+/// The report contains inline SVG charts of the equity curve
+/// compared with the NAVs of the funds, the drawdown, the position
+/// weights of the funds, the cumulative investment compared with the
+/// present value and a list of all trades with their comments. No
+/// JavaScript or external resources are used, so the file can be
+/// viewed offline.
+///
+/// The equity curve is the time-weighted value of one unit invested
+/// at the first day, so that it is not distorted by inflows. Both
+/// log and record must be enabled for the iterator.
+///
+/// # Examples
+/// ```
+/// use eatmud::{read_gta, Fund, NaiveDate, Transaction};
+/// use eatmud::report::write_report;
+/// let hs300 = Fund::from(&read_gta("hs300.txt").unwrap());
+/// let gz2000 = Fund::from(&read_gta("gz2000.txt").unwrap());
+/// let start_date = NaiveDate::parse_from_str("2023-01-01", "%Y-%m-%d").unwrap();
+/// let t = Transaction::new(&[&hs300, &gz2000], Some(start_date), None);
+/// let mut it = t.iter(true, true);
+/// eatmud::strategy::aip_monthly(&mut it, 1, &[1000., 1000.], &[0., 0.]).unwrap();
+/// let mut html = Vec::new();
+/// write_report(&it, "AIP", &mut html).unwrap();
+/// ```
+pub fn write_report<W: Write>(
+    it: &TransactionIterator,
+    title: &str,
+    writer: &mut W,
+) -> Result<(), Box<dyn Error>> {
+    let asset = it
+        .asset_log()
+        .ok_or(ReportError("log is not enabled for transaction iterator"))?;
+    let record = it.record().ok_or(ReportError(
+        "record is not enabled for transaction iterator",
+    ))?;
+    let dates = it.dates();
+    if dates.is_empty() {
+        return Err(Box::new(ReportError(
+            "transaction iterator has not started",
+        )));
+    }
+    let names = it.transaction().names();
+
+    // Total investment of each day.
+    let mut total_investment = vec![0.; dates.len()];
+    let mut k = 0;
+    let mut total = 0.;
+    for (i, date) in dates.iter().enumerate() {
+        while k < record.len() && record[k].date() <= *date {
+            total = record[k].total_investment();
+            k += 1;
+        }
+        total_investment[i] = total;
+    }
+
+    // Log and record have been checked to be enabled.
+    let equity = equity_curve(it).unwrap();
+    let navs = it.navs();
+    let normalized_navs: Vec<Vec<f64>> = (0..it.nfunds())
+        .map(|j| navs.column(j).iter().map(|x| x / navs[[0, j]]).collect())
+        .collect();
+    let mut equity_series = vec![("portfolio", equity.as_slice())];
+    for (name, nav) in names.iter().zip(&normalized_navs) {
+        equity_series.push((name, nav));
+    }
+
+    let drawdowns: Vec<f64> = drawdown(&equity).iter().map(|x| -100. * x).collect();
+
+    let weights: Vec<Vec<f64>> = (0..it.nfunds())
+        .map(|j| {
+            let fund_asset = it.fund_asset_log(j).unwrap();
+            fund_asset
+                .iter()
+                .zip(asset.iter())
+                .map(|(x, y)| if *y == 0. { 0. } else { 100. * x / y })
+                .collect()
+        })
+        .collect();
+    let weight_series: Vec<_> = names
+        .iter()
+        .zip(&weights)
+        .map(|(name, w)| (name.as_str(), w.as_slice()))
+        .collect();
+
+    let asset = asset.to_vec();
+    let value_series = [
+        ("total investment", total_investment.as_slice()),
+        ("present value", asset.as_slice()),
+    ];
+
+    let title = html_escape(title);
+    write!(
+        writer,
+        "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>{}</title>\n\
+         <style>\nbody {{ font-family: sans-serif; margin: 2em; }}\n\
+         table {{ border-collapse: collapse; font-size: 13px; }}\n\
+         th, td {{ border: 1px solid #ccc; padding: 2px 6px; text-align: right; }}\n\
+         td.text {{ text-align: left; }}\n</style>\n</head>\n<body>\n<h1>{}</h1>\n",
+        title, title
+    )?;
+    writeln!(
+        writer,
+        "<p>{} to {}, final asset {:.2}, total investment {:.2}, IRR {}.</p>",
+        dates[0],
+        dates[dates.len() - 1],
+        asset[asset.len() - 1],
+        total,
+        if record.records().iter().any(|rs| rs.investment() != 0.) {
+            format!("{:.2}%", 100. * record.irr_naive())
+        } else {
+            "n/a".to_string()
+        }
+    )?;
+    writeln!(writer, "<h2>Equity curve</h2>")?;
+    writeln!(writer, "{}", line_chart(dates, &equity_series, 2))?;
+    writeln!(writer, "<h2>Drawdown (%)</h2>")?;
+    writeln!(
+        writer,
+        "{}",
+        line_chart(dates, &[("drawdown", &drawdowns)], 1)
+    )?;
+    writeln!(writer, "<h2>Position weights (%)</h2>")?;
+    writeln!(writer, "{}", line_chart(dates, &weight_series, 1))?;
+    writeln!(writer, "<h2>Investment and present value</h2>")?;
+    writeln!(writer, "{}", line_chart(dates, &value_series, 0))?;
+
+    writeln!(writer, "<h2>Trades</h2>")?;
+    writeln!(
+        writer,
+        "<table>\n<tr><th>date</th><th>fund</th><th>investment</th><th>nav</th>\
+         <th>share</th><th>fee</th><th>total share</th><th>comment</th></tr>"
+    )?;
+    let mut trades = Vec::new();
+    for j in 0..it.nfunds() {
+        for rs in it.fund_record(j).unwrap().records() {
+            if rs.investment() != 0. || rs.share() != 0. || !rs.comment().is_empty() {
+                trades.push((rs.date(), j, rs));
+            }
+        }
+    }
+    trades.sort_by_key(|(date, j, _)| (*date, *j));
+    for (date, j, rs) in trades {
+        writeln!(
+            writer,
+            "<tr><td>{}</td><td class=\"text\">{}</td><td>{:.2}</td><td>{:.4}</td>\
+             <td>{:.4}</td><td>{:.2}</td><td>{:.4}</td><td class=\"text\">{}</td></tr>",
+            date,
+            html_escape(&names[j]),
+            rs.investment(),
+            rs.nav(),
+            rs.share(),
+            rs.fee(),
+            rs.total_share(),
+            html_escape(rs.comment())
+        )?;
+    }
+    writeln!(writer, "</table>\n</body>\n</html>")?;
+    Ok(())
+}
+
+/// Draw a line chart as inline SVG.
+///
+/// `precision` is the number of decimal places of the labels of the
+/// y axis.
+fn line_chart(dates: &[NaiveDate], series: &[(&str, &[f64])], precision: usize) -> String {
+    let values = series.iter().flat_map(|(_, s)| s.iter()).copied();
+    let (mut y_min, mut y_max) = values
+        .filter(|x| x.is_finite())
+        .fold((f64::INFINITY, f64::NEG_INFINITY), |(lo, hi), x| {
+            (lo.min(x), hi.max(x))
+        });
+    if y_min > y_max {
+        (y_min, y_max) = (0., 1.);
+    } else if y_min == y_max {
+        (y_min, y_max) = (y_min - 1., y_max + 1.);
+    }
+    let plot_width = WIDTH - MARGIN_LEFT - MARGIN_RIGHT;
+    let plot_height = HEIGHT - MARGIN_TOP - MARGIN_BOTTOM;
+    let n = dates.len();
+    let x_of = |i: usize| {
+        MARGIN_LEFT
+            + if n > 1 {
+                plot_width * i as f64 / (n - 1) as f64
+            } else {
+                0.
+            }
+    };
+    let y_of = |y: f64| MARGIN_TOP + plot_height * (y_max - y) / (y_max - y_min);
+
+    let mut svg = String::new();
+    write!(
+        svg,
+        "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{0}\" height=\"{1}\" \
+         viewBox=\"0 0 {0} {1}\" font-size=\"11\">",
+        WIDTH, HEIGHT
+    )
+    .unwrap();
+    // Grid lines and labels of y axis.
+    for k in 0..=4 {
+        let y = y_min + (y_max - y_min) * k as f64 / 4.;
+        write!(
+            svg,
+            "<line x1=\"{x1:.1}\" y1=\"{y:.1}\" x2=\"{x2:.1}\" y2=\"{y:.1}\" stroke=\"#ddd\"/>\
+             <text x=\"{x0:.1}\" y=\"{y0:.1}\" text-anchor=\"end\">{label:.precision$}</text>",
+            x1 = MARGIN_LEFT,
+            x2 = WIDTH - MARGIN_RIGHT,
+            y = y_of(y),
+            x0 = MARGIN_LEFT - 5.,
+            y0 = y_of(y) + 4.,
+            label = y,
+            precision = precision
+        )
+        .unwrap();
+    }
+    // Labels of x axis.
+    if n > 0 {
+        let nticks = usize::min(n, 6);
+        for k in 0..nticks {
+            let i = if nticks > 1 {
+                (n - 1) * k / (nticks - 1)
+            } else {
+                0
+            };
+            write!(
+                svg,
+                "<text x=\"{:.1}\" y=\"{:.1}\" text-anchor=\"middle\">{}</text>",
+                x_of(i),
+                HEIGHT - MARGIN_BOTTOM + 16.,
+                dates[i]
+            )
+            .unwrap();
+        }
+    }
+    write!(
+        svg,
+        "<rect x=\"{:.1}\" y=\"{:.1}\" width=\"{:.1}\" height=\"{:.1}\" fill=\"none\" stroke=\"#999\"/>",
+        MARGIN_LEFT, MARGIN_TOP, plot_width, plot_height
+    )
+    .unwrap();
+    // Lines and legend.
+    for (k, (name, values)) in series.iter().enumerate() {
+        let color = COLORS[k % COLORS.len()];
+        let points: Vec<String> = values
+            .iter()
+            .enumerate()
+            .filter(|(_, y)| y.is_finite())
+            .map(|(i, y)| format!("{:.1},{:.1}", x_of(i), y_of(*y)))
+            .collect();
+        write!(
+            svg,
+            "<polyline fill=\"none\" stroke=\"{}\" stroke-width=\"1.2\" points=\"{}\"/>\
+             <rect x=\"{:.1}\" y=\"8\" width=\"12\" height=\"10\" fill=\"{0}\"/>\
+             <text x=\"{:.1}\" y=\"17\">{}</text>",
+            color,
+            points.join(" "),
+            MARGIN_LEFT + 150. * k as f64,
+            MARGIN_LEFT + 150. * k as f64 + 16.,
+            html_escape(name)
+        )
+        .unwrap();
+    }
+    svg.push_str("</svg>");
+    svg
+}
+
+fn html_escape(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::*;
+
+    #[test]
+    fn test_report() {
+        let hs300 = Fund::from(&read_gta("hs300.txt").unwrap());
+        let gz2000 = Fund::from(&read_gta("gz2000.txt").unwrap());
+        let start_date = NaiveDate::parse_from_str("2023-01-01", "%Y-%m-%d").unwrap();
+        let t = Transaction::new(&[&hs300, &gz2000], Some(start_date), None);
+        let mut it = t.iter(true, true);
+        it.inflow(1000.).unwrap();
+        it.buy_comment(0, 500., 0., "buy <hs300>").unwrap();
+        while it.next_month(None).is_some() {}
+        let mut buffer = Vec::new();
+        write_report(&it, "Test & report", &mut buffer).unwrap();
+        let html = String::from_utf8(buffer).unwrap();
+        assert!(html.contains("<title>Test &amp; report</title>"));
+        assert_eq!(html.matches("<svg").count(), 4);
+        assert!(html.contains("buy &lt;hs300&gt;"));
+        assert!(!html.contains("<script"));
+
+        let it = t.iter(false, true);
+        assert!(write_report(&it, "", &mut Vec::new()).is_err());
+    }
+}
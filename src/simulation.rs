@@ -0,0 +1,423 @@
+use std::error::Error;
+
+use chrono::NaiveDate;
+use ndarray::{s, Array1, Array2, ArrayView2};
+
+use crate::analysis::{sweep, Distribution, Metric, Metrics, ParamGrid};
+use crate::utility::{covariance, DAYS_PER_YEAR};
+use crate::{Fund, Transaction, TransactionIterator};
+
+#[derive(Debug)]
+pub struct SimulationError(&'static str);
+
+impl std::fmt::Display for SimulationError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        writeln!(f, "SimulationError: {}", self.0)
+    }
+}
+
+impl std::error::Error for SimulationError {}
+
+/// Seedable pseudo random number generator (xoshiro256**).
+///
+/// The same seed always gives the same sequence on all platforms, so
+/// that simulations are reproducible.
+#[derive(Debug, Clone)]
+pub struct Rng {
+    state: [u64; 4],
+}
+
+impl Rng {
+    pub fn new(seed: u64) -> Self {
+        // Expand the seed by splitmix64, so that close seeds give
+        // unrelated states.
+        let mut x = seed;
+        let mut state = [0; 4];
+        for s in state.iter_mut() {
+            x = x.wrapping_add(0x9e3779b97f4a7c15);
+            let mut z = x;
+            z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+            z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+            *s = z ^ (z >> 31);
+        }
+        Rng { state }
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        let result = self.state[1].wrapping_mul(5).rotate_left(7).wrapping_mul(9);
+        let t = self.state[1] << 17;
+        self.state[2] ^= self.state[0];
+        self.state[3] ^= self.state[1];
+        self.state[1] ^= self.state[2];
+        self.state[0] ^= self.state[3];
+        self.state[2] ^= t;
+        self.state[3] = self.state[3].rotate_left(45);
+        result
+    }
+
+    /// Uniform random number in `[0, 1)`.
+    pub fn uniform(&mut self) -> f64 {
+        (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+    }
+
+    /// Uniform random integer in `[0, n)`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `n` is 0.
+    pub fn below(&mut self, n: usize) -> usize {
+        ((self.uniform() * n as f64) as usize).min(n - 1)
+    }
+
+    /// Standard normal random number by the Box-Muller transform.
+    pub fn normal(&mut self) -> f64 {
+        let u1 = 1. - self.uniform();
+        let u2 = self.uniform();
+        (-2. * u1.ln()).sqrt() * (2. * std::f64::consts::PI * u2).cos()
+    }
+}
+
+/// Number of observations per year of the consecutive dates.
+fn periods_per_year(dates: &[NaiveDate]) -> f64 {
+    let days = (dates[dates.len() - 1] - dates[0]).num_days() as f64;
+    (dates.len() - 1) as f64 / (days / DAYS_PER_YEAR)
+}
+
+/// Lower triangular Cholesky factor of a positive semi-definite matrix.
+fn cholesky(a: &Array2<f64>) -> Array2<f64> {
+    let n = a.shape()[0];
+    let mut l = Array2::<f64>::zeros((n, n));
+    for i in 0..n {
+        for j in 0..=i {
+            let sum: f64 = (0..j).map(|k| l[[i, k]] * l[[j, k]]).sum();
+            if i == j {
+                l[[i, j]] = f64::max(a[[i, i]] - sum, 0.).sqrt();
+            } else if l[[j, j]] > 0. {
+                l[[i, j]] = (a[[i, j]] - sum) / l[[j, j]];
+            }
+        }
+    }
+    l
+}
+
+/// Multi-asset geometric Brownian motion.
+///
+/// Log returns of the funds follow a multivariate normal distribution,
+/// whose parameters are annualized by the number of trading days per
+/// year.
+#[derive(Debug, Clone)]
+pub struct Gbm {
+    /// Annual mean of log returns of each fund.
+    pub drift: Array1<f64>,
+    /// Annual covariance of log returns.
+    pub covariance: Array2<f64>,
+}
+
+impl Gbm {
+    /// Fit the parameters by the daily log returns of all the funds
+    /// in `trans`, which should have at least 3 days.
+    pub fn fit(trans: &Transaction) -> Result<Self, SimulationError> {
+        if trans.ndays() < 3 {
+            return Err(SimulationError("at least 3 days are required to fit GBM"));
+        }
+        let navs = trans.navs();
+        let returns = (&navs.slice(s![1.., ..]) / &navs.slice(s![..-1, ..])).mapv(f64::ln);
+        let (mean, cov) = covariance(returns.view());
+        let periods = periods_per_year(trans.date());
+        Ok(Gbm {
+            drift: mean * periods,
+            covariance: cov * periods,
+        })
+    }
+
+    /// Annual volatility of each fund.
+    pub fn volatility(&self) -> Array1<f64> {
+        self.covariance.diag().mapv(f64::sqrt)
+    }
+
+    /// Generate NAVs on `dates`, starting from `initial`.
+    ///
+    /// Each row of the returned array is a date and each column is a
+    /// fund.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `dates` is empty.
+    pub fn generate(&self, dates: &[NaiveDate], initial: &[f64], rng: &mut Rng) -> Array2<f64> {
+        let nfunds = initial.len();
+        let mut navs = Array2::zeros((dates.len(), nfunds));
+        navs.row_mut(0).assign(&Array1::from(initial.to_vec()));
+        if dates.len() < 2 {
+            return navs;
+        }
+        let periods = periods_per_year(dates);
+        let mean = &self.drift / periods;
+        let l = cholesky(&(&self.covariance / periods));
+        for t in 1..dates.len() {
+            let z = Array1::from_iter((0..nfunds).map(|_| rng.normal()));
+            let r = &mean + &l.dot(&z);
+            for j in 0..nfunds {
+                navs[[t, j]] = navs[[t - 1, j]] * r[j].exp();
+            }
+        }
+        navs
+    }
+}
+
+/// Indices of a stationary block bootstrap of `n` samples.
+///
+/// Blocks start at random indices and have geometrically distributed
+/// lengths with mean `mean_block`. Blocks wrap around the end of the
+/// samples, so that all the samples are equally likely to be chosen.
+/// Returns an empty vector if `n` is 0.
+pub fn block_bootstrap_indices(n: usize, mean_block: f64, rng: &mut Rng) -> Vec<usize> {
+    if n == 0 {
+        return Vec::new();
+    }
+    let p = 1. / mean_block.max(1.);
+    let mut indices = Vec::with_capacity(n);
+    let mut idx = rng.below(n);
+    for _ in 0..n {
+        indices.push(idx);
+        idx = if rng.uniform() < p {
+            rng.below(n)
+        } else {
+            (idx + 1) % n
+        };
+    }
+    indices
+}
+
+/// Resample daily returns of NAVs by the stationary block bootstrap.
+///
+/// The same days are drawn for all the columns, so that the
+/// correlation across the funds is preserved. `navs` should have at
+/// least 2 rows.
+pub fn block_bootstrap(
+    navs: ArrayView2<f64>,
+    mean_block: f64,
+    rng: &mut Rng,
+) -> Result<Array2<f64>, SimulationError> {
+    if navs.shape()[0] < 2 {
+        return Err(SimulationError("at least 2 days are required to bootstrap"));
+    }
+    let ratios = &navs.slice(s![1.., ..]) / &navs.slice(s![..-1, ..]);
+    let indices = block_bootstrap_indices(ratios.shape()[0], mean_block, rng);
+    let mut res = Array2::zeros(navs.raw_dim());
+    res.row_mut(0).assign(&navs.row(0));
+    for (t, &k) in indices.iter().enumerate() {
+        for j in 0..navs.shape()[1] {
+            res[[t + 1, j]] = res[[t, j]] * ratios[[k, j]];
+        }
+    }
+    Ok(res)
+}
+
+/// Model generating synthetic NAVs.
+#[derive(Debug, Clone)]
+pub enum PathModel {
+    Gbm(Gbm),
+    /// Joint stationary block bootstrap of historical daily returns,
+    /// with the given mean block length in days.
+    BlockBootstrap(f64),
+}
+
+/// Generate synthetic funds on the dates of `trans`.
+///
+/// The synthetic funds have the same names, codes and initial NAVs
+/// as those of `trans`. A single fund can be simulated by a
+/// transaction of it, such as `Transaction::from_funds(&[&hs300])`.
+/// `trans` should have at least 2 days.
+pub fn synthetic_funds(
+    trans: &Transaction,
+    model: &PathModel,
+    rng: &mut Rng,
+) -> Result<Vec<Fund>, SimulationError> {
+    if trans.ndays() < 2 {
+        return Err(SimulationError("transaction should have at least 2 days"));
+    }
+    let navs = match model {
+        PathModel::Gbm(gbm) => {
+            let initial = trans.navs().row(0).to_vec();
+            gbm.generate(trans.date(), &initial, rng)
+        }
+        PathModel::BlockBootstrap(mean_block) => {
+            block_bootstrap(trans.navs().view(), *mean_block, rng)?
+        }
+    };
+    Ok((0..trans.nfunds())
+        .map(|j| {
+            let mut fund = Fund::new(&trans.names()[j], &trans.codes()[j]);
+            for (t, &date) in trans.date().iter().enumerate() {
+                fund.append(date, navs[[t, j]]);
+            }
+            fund
+        })
+        .collect())
+}
+
+/// Metrics of a strategy over the simulated paths.
+#[derive(Debug, Clone)]
+pub struct MonteCarlo {
+    /// Seed of each path, which reproduces the path by `Rng::new`.
+    pub seeds: Vec<u64>,
+    /// Metrics of each path, or the error message if it fails.
+    pub metrics: Vec<Result<Metrics, String>>,
+}
+
+impl MonteCarlo {
+    /// Distribution of `metric` over the successful paths, or `None`
+    /// if all the paths fail. The worst path is reproduced by
+    /// `Rng::new(self.seeds[worst])`.
+    pub fn distribution(&self, metric: Metric, qs: &[f64]) -> Option<Distribution> {
+        let paths = self.metrics.iter().enumerate();
+        Distribution::new(
+            paths.filter_map(|(k, m)| Some((k, m.as_ref().ok()?))),
+            metric,
+            qs,
+        )
+    }
+}
+
+/// Evaluate a strategy over synthetic paths.
+///
+/// For each path, synthetic funds are generated by `model` on the
+/// dates of `trans`, and `f` runs the strategy on a new transaction
+/// of them from the first day. The paths run in parallel by `sweep`,
+/// and the seed of the `k`-th path is `seed + k`, so the results only
+/// depend on `seed` regardless of the threads.
+///
+/// # Arguments
+///
+/// * `trans` - The transaction providing the dates and history.
+/// * `model` - Model generating the paths.
+/// * `npaths` - Number of paths.
+/// * `seed` - Seed of the first path.
+/// * `nthreads` - Number of threads for `sweep`.
+/// * `f` - Function running the strategy.
+pub fn monte_carlo<F>(
+    trans: &Transaction,
+    model: &PathModel,
+    npaths: usize,
+    seed: u64,
+    nthreads: usize,
+    f: F,
+) -> Result<MonteCarlo, Box<dyn Error>>
+where
+    F: Fn(&mut TransactionIterator) -> Result<(), Box<dyn Error>> + Sync,
+{
+    if trans.ndays() < 2 {
+        return Err(Box::new(SimulationError(
+            "transaction should have at least 2 days",
+        )));
+    }
+    let seeds: Vec<u64> = (0..npaths as u64).map(|k| seed.wrapping_add(k)).collect();
+    let grid = ParamGrid::new().add("path", &(0..npaths).map(|k| k as f64).collect::<Vec<_>>());
+    let table = sweep(trans, &grid, nthreads, |trans, params| {
+        let mut rng = Rng::new(seeds[params["path"] as usize]);
+        let funds = synthetic_funds(trans, model, &mut rng)?;
+        let funds: Vec<&Fund> = funds.iter().collect();
+        let synthetic = Transaction::from_funds(&funds);
+        let mut it = synthetic.iter(true, true);
+        f(&mut it)?;
+        while it.next_day().is_some() {}
+        Ok(Metrics::new(&it)?)
+    })?;
+    Ok(MonteCarlo {
+        seeds,
+        metrics: table.rows().iter().map(|row| row.metrics.clone()).collect(),
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::utility::simple_returns;
+    use crate::*;
+
+    #[test]
+    fn test_synthetic_funds() {
+        let hs300 = Fund::from(&read_gta("hs300.txt").unwrap());
+        let gz2000 = Fund::from(&read_gta("gz2000.txt").unwrap());
+        let start_date = NaiveDate::parse_from_str("20110101", "%Y%m%d").unwrap();
+        let end_date = NaiveDate::parse_from_str("20240101", "%Y%m%d").unwrap();
+        let trans = Transaction::new(&[&hs300, &gz2000], Some(start_date), Some(end_date));
+        let corr = |navs: ArrayView2<f64>| {
+            let (_, cov) = covariance(simple_returns(navs).view());
+            cov[[0, 1]] / (cov[[0, 0]] * cov[[1, 1]]).sqrt()
+        };
+        let historical = corr(trans.navs().view());
+
+        // Deterministic by seed.
+        let gbm = Gbm::fit(&trans).unwrap();
+        let a = gbm.generate(trans.date(), &[1., 1.], &mut Rng::new(42));
+        let b = gbm.generate(trans.date(), &[1., 1.], &mut Rng::new(42));
+        let c = gbm.generate(trans.date(), &[1., 1.], &mut Rng::new(43));
+        assert_eq!(a, b);
+        assert_ne!(a, c);
+        // Fitting the generated path gives similar parameters.
+        let funds =
+            synthetic_funds(&trans, &PathModel::Gbm(gbm.clone()), &mut Rng::new(1)).unwrap();
+        assert_eq!(funds[0].len(), trans.ndays());
+        assert_eq!(funds[1].name(), gz2000.name());
+        let synthetic = Transaction::from_funds(&[&funds[0], &funds[1]]);
+        let fitted = Gbm::fit(&synthetic).unwrap();
+        let diff = &fitted.volatility() - &gbm.volatility();
+        assert!(diff.iter().all(|d| d.abs() < 0.02));
+        assert!((corr(synthetic.navs().view()) - historical).abs() < 0.05);
+
+        // Bootstrap keeps the cross-correlation and the range of daily
+        // returns.
+        let navs = block_bootstrap(trans.navs().view(), 20., &mut Rng::new(7)).unwrap();
+        assert!((corr(navs.view()) - historical).abs() < 0.05);
+        let original = simple_returns(trans.navs().view());
+        let resampled = simple_returns(navs.view());
+        let min = original.iter().copied().fold(f64::INFINITY, f64::min);
+        let max = original.iter().copied().fold(f64::NEG_INFINITY, f64::max);
+        assert!(resampled.iter().all(|&x| x > min - 1e-9 && x < max + 1e-9));
+    }
+
+    #[test]
+    fn test_too_few_days() {
+        let hs300 = Fund::from(&read_gta("hs300.txt").unwrap());
+        let start_date = NaiveDate::parse_from_str("20240102", "%Y%m%d").unwrap();
+        let end_date = NaiveDate::parse_from_str("20240105", "%Y%m%d").unwrap();
+        let trans = Transaction::new(&[&hs300], Some(start_date), Some(end_date));
+        assert_eq!(trans.ndays(), 3);
+        let mut rng = Rng::new(0);
+        assert!(block_bootstrap_indices(0, 5., &mut rng).is_empty());
+        let one_day = trans.slice(None, Some(trans.date()[1]));
+        assert!(block_bootstrap(one_day.navs().view(), 5., &mut rng).is_err());
+        let model = PathModel::BlockBootstrap(5.);
+        assert!(synthetic_funds(&one_day, &model, &mut rng).is_err());
+        assert!(Gbm::fit(&trans.slice(None, Some(trans.date()[2]))).is_err());
+        let gbm = Gbm::fit(&trans).unwrap();
+        assert!(gbm.drift.iter().all(|x| x.is_finite()));
+        assert!(gbm.volatility().iter().all(|x| x.is_finite()));
+        let navs = gbm.generate(&trans.date()[..1], &[1.], &mut rng);
+        assert_eq!(navs, Array2::from_elem((1, 1), 1.));
+    }
+
+    #[test]
+    fn test_monte_carlo() {
+        let hs300 = Fund::from(&read_gta("hs300.txt").unwrap());
+        let start_date = NaiveDate::parse_from_str("20140101", "%Y%m%d").unwrap();
+        let end_date = NaiveDate::parse_from_str("20240101", "%Y%m%d").unwrap();
+        let trans = Transaction::new(&[&hs300], Some(start_date), Some(end_date));
+        let aip = |it: &mut TransactionIterator| strategy::aip_monthly(it, 1, &[1000.], &[0.]);
+        let model = PathModel::BlockBootstrap(20.);
+        let a = monte_carlo(&trans, &model, 64, 2024, 0, aip).unwrap();
+        let b = monte_carlo(&trans, &model, 64, 2024, 3, aip).unwrap();
+        assert_eq!(a.metrics, b.metrics);
+        let dist = a.distribution(Metric::Irr, &[0.05, 0.5, 0.95]).unwrap();
+        assert_eq!(dist.count, 64);
+        assert!(dist.quantiles[0].1 < dist.quantiles[2].1);
+        // The worst path is reproduced by its seed.
+        let mut rng = Rng::new(a.seeds[dist.worst]);
+        let funds = synthetic_funds(&trans, &model, &mut rng).unwrap();
+        let synthetic = Transaction::from_funds(&[&funds[0]]);
+        let mut it = synthetic.iter(true, true);
+        aip(&mut it).unwrap();
+        assert_eq!(Metrics::new(&it).unwrap().irr, dist.min);
+    }
+}
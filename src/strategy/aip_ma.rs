@@ -0,0 +1,136 @@
+use crate::utility::interp;
+use crate::TransactionIterator;
+use ndarray::s;
+
+#[derive(Debug)]
+pub struct AIPMAError(&'static str);
+
+impl std::fmt::Display for AIPMAError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        writeln!(f, "AIPMAError: {}", self.0)
+    }
+}
+
+impl std::error::Error for AIPMAError {}
+
+/// Automatic investment plan scaled by deviation from moving average.
+///
+/// Invests monthly like `aip_monthly`, but the amount of each fund
+/// is multiplied by a factor interpolated from the deviation of its
+/// NAV from the moving average of the last `window` days, which is
+/// `nav / ma - 1`. Only NAVs before the transaction date are used.
+/// Until `window` days have passed, the multiplier is 1.
+///
+/// # Arguments
+///
+/// * `day` - Day of month to invest.
+/// * `window` - Number of days of the moving average.
+/// * `amounts` - Base amounts to invest for each fund.
+/// * `fees` - Fees of each transaction for each fund.
+/// * `curve` - Points of `(deviation, multiplier)` sorted by
+///   deviation. Multipliers between the points are interpolated
+///   linearly and clamped outside. For example,
+///   `[(-0.2, 2.), (0., 1.), (0.2, 0.)]` invests double when the NAV
+///   is 20% below the moving average and stops when it is 20% above.
+/// * `caps` - Maximal amount of each investment for each fund.
+pub fn aip_ma_deviation(
+    it: &mut TransactionIterator,
+    day: u32,
+    window: usize,
+    amounts: &[f64],
+    fees: &[f64],
+    curve: &[(f64, f64)],
+    caps: &[f64],
+) -> Result<(), Box<dyn std::error::Error>> {
+    if window == 0 || curve.is_empty() {
+        return Err(Box::new(AIPMAError("window and curve should not be empty")));
+    }
+    while it.next_month(Some(day)).is_some() {
+        for j in 0..it.nfunds() {
+            let navs = it.navs();
+            let (multiplier, comment) = if navs.shape()[0] < window {
+                (1., "insufficient history".to_string())
+            } else {
+                let y = navs.slice(s![-(window as isize).., j]);
+                let ma = y.mean().unwrap();
+                let deviation = y[window - 1] / ma - 1.;
+                let multiplier = interp(curve, deviation);
+                (
+                    multiplier,
+                    format!("deviation {:.2}%, x{:.2}", 100. * deviation, multiplier),
+                )
+            };
+            let amount = f64::min(amounts[j] * multiplier, caps[j]);
+            if amount > 0. {
+                it.inflow(amount)?;
+                it.buy_comment(j, amount, fees[j], &comment)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::strategy::aip_monthly;
+    use crate::*;
+
+    #[test]
+    fn test_aip_ma_1() {
+        let hs300 = Fund::from(&read_gta("hs300.txt").unwrap());
+        let gz2000 = Fund::from(&read_gta("gz2000.txt").unwrap());
+        let start_date = NaiveDate::parse_from_str("20110101", "%Y%m%d").unwrap();
+        let end_date = NaiveDate::parse_from_str("20240101", "%Y%m%d").unwrap();
+        let trans = Transaction::new(&[&hs300, &gz2000], Some(start_date), Some(end_date));
+        for day in [1, 15, 28] {
+            let mut it1 = trans.iter(false, false);
+            aip_monthly(&mut it1, day, &[1000.; 2], &[2.; 2]).unwrap();
+            let mut it2 = trans.iter(false, false);
+            aip_ma_deviation(
+                &mut it2,
+                day,
+                250,
+                &[1000.; 2],
+                &[2.; 2],
+                &[(0., 1.)],
+                &[f64::INFINITY; 2],
+            )
+            .unwrap();
+            assert!((it1.asset() - it2.asset()).abs() < 1e-6);
+        }
+    }
+
+    #[test]
+    fn test_aip_ma_2() {
+        let hs300 = Fund::from(&read_gta("hs300.txt").unwrap());
+        let gz2000 = Fund::from(&read_gta("gz2000.txt").unwrap());
+        let start_date = NaiveDate::parse_from_str("20110101", "%Y%m%d").unwrap();
+        let end_date = NaiveDate::parse_from_str("20240101", "%Y%m%d").unwrap();
+        let trans = Transaction::new(&[&hs300, &gz2000], Some(start_date), Some(end_date));
+        let curve = [(-0.3, 3.), (0., 1.), (0.3, 0.)];
+        let mut results = Vec::new();
+        for day in 1..29 {
+            let mut it = trans.iter(true, true);
+            aip_ma_deviation(
+                &mut it,
+                day,
+                250,
+                &[1000.; 2],
+                &[2.; 2],
+                &curve,
+                &[2500.; 2],
+            )
+            .unwrap();
+            let record = it.fund_record(0).unwrap();
+            assert!(record.records().iter().all(|rs| rs.investment() <= 2500.));
+            let rec = it.record().unwrap();
+            results.push(rec.irr_naive());
+        }
+        assert!((results[0] - 0.03795).abs() < 1e-5);
+        assert!((results[1] - 0.03697).abs() < 1e-5);
+        assert!((results[2] - 0.03678).abs() < 1e-5);
+        assert!((results[results.len() - 2] - 0.02903).abs() < 1e-5);
+        assert!((results[results.len() - 1] - 0.03200).abs() < 1e-5);
+    }
+}
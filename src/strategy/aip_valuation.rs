@@ -0,0 +1,160 @@
+use crate::utility::{search_sorted, step_lookup};
+use crate::{DataSlice, Fund, TransactionIterator};
+
+#[derive(Debug)]
+pub struct AIPValuationError(&'static str);
+
+impl std::fmt::Display for AIPValuationError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        writeln!(f, "AIPValuationError: {}", self.0)
+    }
+}
+
+impl std::error::Error for AIPValuationError {}
+
+/// Get the latest valuation before `date`.
+fn latest_valuation(valuation: &Fund, date: chrono::NaiveDate) -> Option<f64> {
+    let idx = search_sorted(valuation.data(), &date, |s| s.date(), None);
+    if idx == 0 {
+        None
+    } else {
+        Some(valuation[idx - 1].value())
+    }
+}
+
+/// Automatic investment plan scaled by valuation.
+///
+/// Invests monthly like `aip_monthly`, but the amount of each fund
+/// is multiplied by a factor looked up from its valuation, such as
+/// the PE percentile read by `read_gta_column`. The valuation of the
+/// last day before the transaction date is used. If no valuation is
+/// available yet, the multiplier is 1.
+///
+/// # Arguments
+///
+/// * `day` - Day of month to invest.
+/// * `amounts` - Base amounts to invest for each fund.
+/// * `fees` - Fees of each transaction for each fund.
+/// * `valuations` - Valuation series for each fund.
+/// * `multipliers` - Step table of `(bound, multiplier)` sorted by
+///   `bound`. The multiplier of the first item whose bound is larger
+///   than the valuation is used, and zero is used if the valuation
+///   exceeds all the bounds. For example, `[(20., 2.), (80., 1.)]`
+///   invests double below the 20th percentile and stops above the
+///   80th percentile.
+/// * `take_profit` - Optional `(bound, fraction)`. When the valuation
+///   is not less than `bound`, `fraction` of the shares of the fund
+///   are sold at the investment day. The income is kept as cash.
+pub fn aip_valuation(
+    it: &mut TransactionIterator,
+    day: u32,
+    amounts: &[f64],
+    fees: &[f64],
+    valuations: &[&Fund],
+    multipliers: &[(f64, f64)],
+    take_profit: Option<(f64, f64)>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    if valuations.len() != it.nfunds() {
+        return Err(Box::new(AIPValuationError(
+            "number of valuations does not match number of funds",
+        )));
+    }
+    while it.next_month(Some(day)).is_some() {
+        for j in 0..it.nfunds() {
+            let Some(valuation) = latest_valuation(valuations[j], it.today()) else {
+                it.inflow(amounts[j])?;
+                it.buy_comment(j, amounts[j], fees[j], "no valuation")?;
+                continue;
+            };
+            if let Some((bound, fraction)) = take_profit {
+                if valuation >= bound && it.share(j) > 0. {
+                    it.sell_comment(
+                        j,
+                        it.share(j) * fraction,
+                        fees[j],
+                        &format!("take profit at valuation {:.2}", valuation),
+                    )?;
+                }
+            }
+            let multiplier = step_lookup(multipliers, valuation);
+            if multiplier > 0. {
+                let amount = amounts[j] * multiplier;
+                it.inflow(amount)?;
+                it.buy_comment(
+                    j,
+                    amount,
+                    fees[j],
+                    &format!("valuation {:.2}, x{:.2}", valuation, multiplier),
+                )?;
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::data::{read_gta_column, GTA_PE_PERCENTILE};
+    use crate::strategy::aip_monthly;
+    use crate::*;
+
+    #[test]
+    fn test_aip_valuation_1() {
+        let hs300 = Fund::from(&read_gta("hs300.txt").unwrap());
+        let gz2000 = Fund::from(&read_gta("gz2000.txt").unwrap());
+        let start_date = NaiveDate::parse_from_str("20110101", "%Y%m%d").unwrap();
+        let end_date = NaiveDate::parse_from_str("20240101", "%Y%m%d").unwrap();
+        let trans = Transaction::new(&[&hs300, &gz2000], Some(start_date), Some(end_date));
+        let mut it1 = trans.iter(false, false);
+        aip_monthly(&mut it1, 5, &[1000.; 2], &[2.; 2]).unwrap();
+        let mut it2 = trans.iter(false, false);
+        aip_valuation(
+            &mut it2,
+            5,
+            &[1000.; 2],
+            &[2.; 2],
+            &[&hs300, &gz2000],
+            &[(f64::INFINITY, 1.)],
+            None,
+        )
+        .unwrap();
+        assert!((it1.asset() - it2.asset()).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_aip_valuation_2() {
+        let hs300 = Fund::from(&read_gta("hs300.txt").unwrap());
+        let pe = read_gta_column("hs300.txt", GTA_PE_PERCENTILE).unwrap();
+        let start_date = NaiveDate::parse_from_str("20200301", "%Y%m%d").unwrap();
+        let end_date = NaiveDate::parse_from_str("20240101", "%Y%m%d").unwrap();
+        let trans = Transaction::new(&[&hs300], Some(start_date), Some(end_date));
+        let multipliers = [(20., 2.), (50., 1.5), (80., 1.)];
+        let mut it = trans.iter(true, true);
+        aip_valuation(
+            &mut it,
+            1,
+            &[1000.],
+            &[0.],
+            &[&pe],
+            &multipliers,
+            Some((90., 0.5)),
+        )
+        .unwrap();
+        let record = it.fund_record(0).unwrap();
+        let investments: Vec<_> = record.records().iter().map(|rs| rs.investment()).collect();
+        // Expensive in 2020 and 2021, cheap in 2023.
+        assert!(investments.iter().take(12).any(|&x| x < 0.));
+        assert!(investments.iter().rev().take(2).all(|&x| x == 2000.));
+        assert!(record
+            .records()
+            .iter()
+            .any(|rs| rs.comment().starts_with("take profit")));
+
+        let mut it_aip = trans.iter(true, true);
+        aip_monthly(&mut it_aip, 1, &[1000.], &[0.]).unwrap();
+        let irr = it.record().unwrap().irr_naive();
+        let irr_aip = it_aip.record().unwrap().irr_naive();
+        assert!(irr > irr_aip);
+    }
+}
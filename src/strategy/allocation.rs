@@ -0,0 +1,222 @@
+use crate::strategy::rebalance::rebalance_to_weights;
+use crate::utility::{covariance, maximize_quadratic, simple_returns};
+use crate::{Schedule, TransactionIterator};
+use ndarray::{s, Array1, Array2, ArrayView2};
+
+#[derive(Debug)]
+pub struct AllocationError(&'static str);
+
+impl std::fmt::Display for AllocationError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        writeln!(f, "AllocationError: {}", self.0)
+    }
+}
+
+impl std::error::Error for AllocationError {}
+
+/// Method of allocation by risk.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Allocation {
+    /// Weights proportional to the inverse of volatility.
+    InverseVolatility,
+    /// Equal risk contribution, where each fund contributes the same
+    /// variance to the portfolio.
+    RiskParity,
+    /// Long-only portfolio with minimal variance.
+    MinimumVariance,
+}
+
+pub struct AllocationIndicator {
+    /// Weights of each fund, which sum to 1.
+    pub weights: Array1<f64>,
+    /// Covariance matrix of daily returns.
+    pub covariance: Array2<f64>,
+    /// Fraction of portfolio variance contributed by each fund.
+    pub risk_contributions: Array1<f64>,
+}
+
+/// Floor of the volatility of daily returns for inverse volatility
+/// weighting, so that funds with flat NAVs get finite weights.
+const MIN_VOLATILITY: f64 = 1e-4;
+
+/// Weights proportional to the inverse of the volatility of each
+/// column of `returns`, which sum to 1. The volatility is floored at
+/// `MIN_VOLATILITY`.
+pub(crate) fn inverse_volatility(returns: ArrayView2<f64>) -> Array1<f64> {
+    let w: Array1<f64> = returns
+        .columns()
+        .into_iter()
+        .map(|r| 1. / f64::max(r.std(1.), MIN_VOLATILITY))
+        .collect();
+    let total = w.sum();
+    w / total
+}
+
+/// Solve the weights of equal risk contribution.
+///
+/// Minimizes `x' cov x / 2 - sum(ln(x))` by cyclical coordinate
+/// descent, whose solution normalized to sum 1 has equal risk
+/// contribution.
+fn risk_parity(cov: &Array2<f64>, tol: f64, maxiter: usize) -> Array1<f64> {
+    let n = cov.shape()[0];
+    let mut x = Array1::from_iter((0..n).map(|i| 1. / cov[[i, i]].sqrt()));
+    for _ in 0..maxiter {
+        let mut change: f64 = 0.;
+        for i in 0..n {
+            let c = cov.row(i).dot(&x) - cov[[i, i]] * x[i];
+            let new_xi = (-c + (c * c + 4. * cov[[i, i]]).sqrt()) / (2. * cov[[i, i]]);
+            change = change.max((new_xi - x[i]).abs() / new_xi);
+            x[i] = new_xi;
+        }
+        if change < tol {
+            break;
+        }
+    }
+    let total = x.sum();
+    x / total
+}
+
+/// Get the weights of all funds by `allocation`.
+///
+/// The covariance is estimated by the daily returns of the last
+/// `window` days before today. This function provides an inspection
+/// into `allocation_schedule` during iteration, like `kelly_hint`.
+pub fn allocation_hint(
+    it: &TransactionIterator,
+    allocation: Allocation,
+    window: usize,
+) -> Result<AllocationIndicator, Box<dyn std::error::Error>> {
+    let navs = it.navs();
+    let n = navs.shape()[0];
+    if window < 2 || n <= window {
+        return Err(Box::new(AllocationError(
+            "window too large for allocation or less than 2",
+        )));
+    }
+    let returns = simple_returns(navs.slice(s![n - window - 1.., ..]));
+    let (_, cov) = covariance(returns.view());
+    if (0..it.nfunds()).any(|i| cov[[i, i]] <= 0.) {
+        return Err(Box::new(AllocationError("volatility of fund is zero")));
+    }
+    let weights = match allocation {
+        Allocation::InverseVolatility => inverse_volatility(returns.view()),
+        Allocation::RiskParity => risk_parity(&cov, 1e-10, 10000),
+        Allocation::MinimumVariance => {
+            let zeros = Array1::zeros(it.nfunds());
+            maximize_quadratic(zeros.view(), cov.view(), true, 1e-12, 100000)
+        }
+    };
+    let marginal = cov.dot(&weights);
+    let contributions = &weights * &marginal;
+    let variance = contributions.sum();
+    Ok(AllocationIndicator {
+        weights,
+        covariance: cov,
+        risk_contributions: contributions / variance,
+    })
+}
+
+/// Rebalance the funds to the weights given by `allocation_hint` at
+/// each date of `schedule`.
+///
+/// No transaction is made until there are `window` days in history.
+/// Cash should be provided by `inflow` before calling this function.
+///
+/// # Arguments
+///
+/// * `schedule` - Schedule of rebalancing.
+/// * `allocation` - Method of allocation.
+/// * `window` - Number of days to estimate the covariance.
+/// * `fee_rates` - Fee as a fraction of the amount of each
+///   transaction for each fund.
+pub fn allocation_schedule(
+    it: &mut TransactionIterator,
+    schedule: Schedule,
+    allocation: Allocation,
+    window: usize,
+    fee_rates: &[f64],
+) -> Result<(), Box<dyn std::error::Error>> {
+    if window < 2 {
+        return Err(Box::new(AllocationError("window should be at least 2")));
+    }
+    while it.next_schedule(schedule).is_some() {
+        if it.navs().shape()[0] <= window {
+            continue;
+        }
+        let indicator = allocation_hint(it, allocation, window)?;
+        let comment = format!(
+            "{:?} weights = [{}]",
+            allocation,
+            indicator
+                .weights
+                .iter()
+                .map(|w| format!("{:.2}%", 100. * w))
+                .collect::<Vec<_>>()
+                .join(", ")
+        );
+        rebalance_to_weights(
+            it,
+            indicator.weights.as_slice().unwrap(),
+            fee_rates,
+            &comment,
+        )?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::*;
+    use ndarray::array;
+
+    #[test]
+    fn test_risk_parity() {
+        // Uncorrelated funds are weighted by inverse volatility.
+        let cov = array![[0.04, 0.], [0., 0.01]];
+        let w = risk_parity(&cov, 1e-12, 10000);
+        assert!((w[0] - 1. / 3.).abs() < 1e-9);
+        assert!((w[1] - 2. / 3.).abs() < 1e-9);
+        // Equal contributions with correlation.
+        let cov = array![[0.04, 0.006, 0.], [0.006, 0.01, 0.002], [0., 0.002, 0.0025]];
+        let w = risk_parity(&cov, 1e-12, 10000);
+        let rc = &w * &cov.dot(&w);
+        assert!((rc[0] - rc[1]).abs() < 1e-9 && (rc[1] - rc[2]).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_allocation() {
+        let hs300 = Fund::from(&read_gta("hs300.txt").unwrap());
+        let gz2000 = Fund::from(&read_gta("gz2000.txt").unwrap());
+        let start_date = NaiveDate::parse_from_str("20150101", "%Y%m%d").unwrap();
+        let end_date = NaiveDate::parse_from_str("20240101", "%Y%m%d").unwrap();
+        let trans = Transaction::new(&[&hs300, &gz2000], Some(start_date), Some(end_date));
+        let mut it = trans.iter(false, false);
+        it.goto(NaiveDate::parse_from_str("20200101", "%Y%m%d").unwrap());
+
+        let iv = allocation_hint(&it, Allocation::InverseVolatility, 250).unwrap();
+        let rp = allocation_hint(&it, Allocation::RiskParity, 250).unwrap();
+        let mv = allocation_hint(&it, Allocation::MinimumVariance, 250).unwrap();
+        // With two funds, risk parity is the same as inverse volatility.
+        assert!((&iv.weights - &rp.weights).iter().all(|d| d.abs() < 1e-6));
+        assert!((rp.risk_contributions[0] - 0.5).abs() < 1e-6);
+        // Closed form of minimum variance of two funds.
+        let c = &mv.covariance;
+        let w0 = (c[[1, 1]] - c[[0, 1]]) / (c[[0, 0]] + c[[1, 1]] - 2. * c[[0, 1]]);
+        assert!((mv.weights[0] - w0.clamp(0., 1.)).abs() < 1e-6);
+        assert!((mv.weights.sum() - 1.).abs() < 1e-9);
+
+        for allocation in [
+            Allocation::InverseVolatility,
+            Allocation::RiskParity,
+            Allocation::MinimumVariance,
+        ] {
+            let mut it = trans.iter(true, true);
+            it.inflow(10000.).unwrap();
+            allocation_schedule(&mut it, Schedule::Monthly(1), allocation, 120, &[0.001; 2])
+                .unwrap();
+            assert!(it.cash_log().unwrap().iter().all(|&x| x > -1e-9));
+            assert!(it.fund_record(0).unwrap().len() > 100);
+        }
+    }
+}
@@ -0,0 +1,253 @@
+use crate::strategy::rebalance::rebalance_to_weights;
+use crate::utility::interp;
+use crate::TransactionIterator;
+use chrono::NaiveDate;
+
+#[derive(Debug)]
+pub struct GlideError(&'static str);
+
+impl std::fmt::Display for GlideError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        writeln!(f, "GlideError: {}", self.0)
+    }
+}
+
+impl std::error::Error for GlideError {}
+
+/// Shape of the glide path.
+#[derive(Debug, Clone)]
+pub enum GlideCurve {
+    Linear,
+    /// Points of `(time, progress)` sorted by time, where both range
+    /// from 0 to 1. `time` is the fraction of time passed from the
+    /// start date to the target date, and `progress` is the fraction
+    /// of the equity weight moved from the initial weight to the final
+    /// weight. Values between the points are interpolated linearly.
+    Custom(Vec<(f64, f64)>),
+}
+
+/// Equity weight changing from the start date to the target date.
+#[derive(Debug, Clone)]
+pub struct GlidePath {
+    pub start_date: NaiveDate,
+    pub target_date: NaiveDate,
+    /// Equity weight before the start date.
+    pub initial_weight: f64,
+    /// Equity weight after the target date.
+    pub final_weight: f64,
+    pub curve: GlideCurve,
+}
+
+impl GlidePath {
+    /// Equity weight at `date`.
+    pub fn equity_weight(&self, date: NaiveDate) -> f64 {
+        let total = (self.target_date - self.start_date).num_days() as f64;
+        let passed = (date - self.start_date).num_days() as f64;
+        let time = if total > 0. {
+            (passed / total).clamp(0., 1.)
+        } else if passed < 0. {
+            0.
+        } else {
+            1.
+        };
+        let progress = match &self.curve {
+            GlideCurve::Linear => time,
+            GlideCurve::Custom(points) => interp(points, time),
+        };
+        self.initial_weight + (self.final_weight - self.initial_weight) * progress
+    }
+}
+
+/// Target-date glide path strategy.
+///
+/// The transaction should have two funds, where the first is equity
+/// and the second is bond. At the given day of each month, the funds
+/// are first rebalanced to the equity weight of the glide path if
+/// `rebalance_months` months have passed since the last rebalance,
+/// and then `amount` is invested into the funds by the same weights.
+/// The contribution is always made on the scheduled day. In the months
+/// of rebalance, the comments of the funds are joined like
+/// `"rebalance to equity ...; contribution ..."`, and the cash record
+/// has one `"contribution"` entry for each scheduled day.
+///
+/// # Arguments
+///
+/// * `path` - The glide path.
+/// * `day` - Day of month to invest.
+/// * `amount` - Amount to invest each month.
+/// * `rebalance_months` - Number of months between rebalances. Use 0
+///   to never rebalance, where only the contributions follow the
+///   glide path.
+/// * `fee_rates` - Fee as a fraction of the amount of each
+///   transaction for each fund.
+pub fn glide_path(
+    it: &mut TransactionIterator,
+    path: &GlidePath,
+    day: u32,
+    amount: f64,
+    rebalance_months: usize,
+    fee_rates: &[f64],
+) -> Result<(), Box<dyn std::error::Error>> {
+    if it.nfunds() != 2 || fee_rates.len() != 2 {
+        return Err(Box::new(GlideError(
+            "glide path requires one equity fund and one bond fund",
+        )));
+    }
+    let mut months = 0;
+    while it.next_month(Some(day)).is_some() {
+        let equity = path.equity_weight(it.today());
+        let weights = [equity, 1. - equity];
+        months += 1;
+        if rebalance_months > 0 && months % rebalance_months == 0 && it.asset() > 0. {
+            rebalance_to_weights(
+                it,
+                &weights,
+                fee_rates,
+                &format!("rebalance to equity {:.2}%", 100. * equity),
+            )?;
+        }
+        it.inflow_comment(amount, "contribution")?;
+        for (j, w) in weights.iter().enumerate() {
+            let investment = amount * w;
+            if investment > 0. {
+                it.buy_comment(
+                    j,
+                    investment,
+                    investment * fee_rates[j],
+                    &format!(
+                        "contribution {:.2} at equity {:.2}%",
+                        investment,
+                        100. * equity
+                    ),
+                )?;
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::record::RecordSlice;
+    use crate::*;
+    use chrono::Datelike;
+
+    #[test]
+    fn test_equity_weight() {
+        let start_date = NaiveDate::parse_from_str("20200101", "%Y%m%d").unwrap();
+        let target_date = NaiveDate::parse_from_str("20300101", "%Y%m%d").unwrap();
+        let middle = NaiveDate::parse_from_str("20250101", "%Y%m%d").unwrap();
+        let mut path = GlidePath {
+            start_date,
+            target_date,
+            initial_weight: 0.9,
+            final_weight: 0.3,
+            curve: GlideCurve::Linear,
+        };
+        assert_eq!(path.equity_weight(start_date - Duration::days(100)), 0.9);
+        assert!((path.equity_weight(target_date + Duration::days(100)) - 0.3).abs() < 1e-9);
+        assert!((path.equity_weight(middle) - 0.6).abs() < 1e-3);
+        // Keep the initial weight for the first half.
+        path.curve = GlideCurve::Custom(vec![(0., 0.), (0.5, 0.), (1., 1.)]);
+        assert!((path.equity_weight(middle) - 0.9).abs() < 1e-3);
+        assert!((path.equity_weight(target_date) - 0.3).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_glide_path() {
+        let hs300 = Fund::from(&read_gta("hs300.txt").unwrap());
+        // A bond fund growing steadily by 3% per year.
+        let mut bond = Fund::new("bond", "000000");
+        let first_date = hs300[0].date();
+        for s in hs300.data() {
+            let days = (s.date() - first_date).num_days() as f64;
+            bond.append(s.date(), 1.03f64.powf(days / DAYS_PER_YEAR));
+        }
+        let start_date = NaiveDate::parse_from_str("20110101", "%Y%m%d").unwrap();
+        let end_date = NaiveDate::parse_from_str("20240101", "%Y%m%d").unwrap();
+        let trans = Transaction::new(&[&hs300, &bond], Some(start_date), Some(end_date));
+        let path = GlidePath {
+            start_date,
+            target_date: NaiveDate::parse_from_str("20230101", "%Y%m%d").unwrap(),
+            initial_weight: 0.9,
+            final_weight: 0.3,
+            curve: GlideCurve::Linear,
+        };
+        let mut it = trans.iter(true, true);
+        glide_path(&mut it, &path, 1, 1000., 12, &[0.; 2]).unwrap();
+        assert!(it.cash_log().unwrap().iter().all(|&x| x > -1e-6));
+        // Equity weight is close to the final weight after the last
+        // rebalance.
+        let weight = it.fund_asset(0) / it.asset();
+        assert!((weight - 0.3).abs() < 0.05);
+        let record = it.fund_record(0).unwrap();
+        let comments: Vec<_> = record.records().iter().map(|rs| rs.comment()).collect();
+        assert!(comments.iter().any(|c| c.starts_with("contribution")));
+        assert!(comments
+            .iter()
+            .any(|c| c.starts_with("rebalance to equity")));
+        assert!(comments
+            .iter()
+            .any(|c| c.starts_with("rebalance to equity") && c.contains("; contribution")));
+
+        // Without rebalancing, the equity weight stays higher.
+        let mut it = trans.iter(false, false);
+        glide_path(&mut it, &path, 1, 1000., 0, &[0.; 2]).unwrap();
+        assert!(it.fund_asset(0) / it.asset() > weight + 0.1);
+    }
+
+    /// Contributions stay on the scheduled day when the rebalance is
+    /// made on the last trading day of a year.
+    #[test]
+    fn test_glide_path_year_end() {
+        let hs300 = Fund::from(&read_gta("hs300.txt").unwrap());
+        let mut bond = Fund::new("bond", "000000");
+        for s in hs300.data() {
+            bond.append(s.date(), 1.);
+        }
+        let start_date = NaiveDate::parse_from_str("20191101", "%Y%m%d").unwrap();
+        let end_date = NaiveDate::parse_from_str("20200701", "%Y%m%d").unwrap();
+        let trans = Transaction::new(&[&hs300, &bond], Some(start_date), Some(end_date));
+        let path = GlidePath {
+            start_date,
+            target_date: end_date,
+            initial_weight: 0.9,
+            final_weight: 0.3,
+            curve: GlideCurve::Linear,
+        };
+        let contributions = |rebalance_months| {
+            let mut it = trans.iter(false, true);
+            it.inflow(1000.).unwrap().buy(1, 1000., 0.).unwrap();
+            glide_path(&mut it, &path, 31, 1000., rebalance_months, &[0.; 2]).unwrap();
+            let dates: Vec<_> = it
+                .cash_record()
+                .unwrap()
+                .records()
+                .iter()
+                .filter(|rs| rs.comment() == "contribution")
+                .map(|rs| rs.date())
+                .collect();
+            let rebalance = it
+                .fund_record(0)
+                .unwrap()
+                .records()
+                .iter()
+                .find(|rs| rs.date() == NaiveDate::from_ymd_opt(2019, 12, 31).unwrap())
+                .map(|rs| rs.comment().to_string());
+            (dates, rebalance)
+        };
+        // Rebalance on each scheduled day, the first of which is
+        // Dec 31, 2019.
+        let (dates, comment) = contributions(1);
+        assert_eq!(dates, contributions(0).0);
+        // At most one contribution in each month.
+        assert!(dates
+            .windows(2)
+            .all(|w| (w[0].year(), w[0].month()) < (w[1].year(), w[1].month())));
+        assert!(dates.contains(&NaiveDate::from_ymd_opt(2019, 12, 31).unwrap()));
+        let comment = comment.unwrap();
+        assert!(comment.starts_with("rebalance to equity"));
+        assert!(comment.contains("; contribution"));
+    }
+}
@@ -0,0 +1,257 @@
+use crate::TransactionIterator;
+use chrono::NaiveDate;
+
+#[derive(Debug)]
+pub struct GridError(&'static str);
+
+impl std::fmt::Display for GridError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        writeln!(f, "GridError: {}", self.0)
+    }
+}
+
+impl std::error::Error for GridError {}
+
+/// Step between grid levels.
+#[derive(Debug, Clone, Copy)]
+pub enum GridStep {
+    /// Fraction of the base price, e.g. 0.05 for 5%.
+    Percent(f64),
+    /// Absolute price difference.
+    Absolute(f64),
+}
+
+/// Parameters of grid trading for one fund.
+#[derive(Debug, Clone)]
+pub struct GridConfig {
+    /// Price where the first lot is bought.
+    pub base_price: f64,
+    pub step: GridStep,
+    /// Maximal number of lots held at the same time. Use 0 to skip
+    /// the fund.
+    pub max_layers: usize,
+    /// Amount of money to buy each lot, including fee.
+    pub amount: f64,
+    /// Fee as a fraction of the amount of each transaction.
+    pub fee_rate: f64,
+}
+
+impl GridConfig {
+    fn step_size(&self) -> f64 {
+        match self.step {
+            GridStep::Percent(x) => self.base_price * x,
+            GridStep::Absolute(x) => x,
+        }
+    }
+
+    /// Price to buy the next lot, which is one step below the NAV of
+    /// the last fill, or the base price before the first fill.
+    fn buy_level(&self, last_fill: Option<f64>) -> f64 {
+        match last_fill {
+            Some(nav) => nav - self.step_size(),
+            None => self.base_price,
+        }
+    }
+}
+
+/// A lot bought by grid trading.
+struct Lot {
+    date: NaiveDate,
+    nav: f64,
+    shares: f64,
+    cost: f64,
+}
+
+/// Grid trading strategy.
+///
+/// For each fund, the first lot is bought when the price drops to the
+/// base price, and each following lot is bought when the price drops
+/// one step below the last fill, that is the NAV of the last purchase
+/// or sale. A lot is sold when the price rises one step above the NAV
+/// where it was bought, so the last bought lot is always sold first. Prices are
+/// checked daily by the NAV of the previous day and transactions are
+/// made at the NAV of the day. At most one lot is bought each day, so
+/// a gap down buys only one lot. Lots are only bought if there is
+/// enough cash, so cash should be provided by `inflow` before calling
+/// this function.
+///
+/// Comments of the transactions show the lot number, and the profit
+/// of each lot is recorded when it is sold.
+pub fn grid(
+    it: &mut TransactionIterator,
+    configs: &[GridConfig],
+) -> Result<(), Box<dyn std::error::Error>> {
+    if configs.len() != it.nfunds() {
+        return Err(Box::new(GridError(
+            "number of configs does not match number of funds",
+        )));
+    }
+    if configs.iter().any(|c| c.step_size() <= 0.) {
+        return Err(Box::new(GridError("grid step should be positive")));
+    }
+    let mut lots: Vec<Vec<Lot>> = (0..it.nfunds()).map(|_| Vec::new()).collect();
+    let mut last_fill: Vec<Option<f64>> = vec![None; it.nfunds()];
+    while it.next_day().is_some() {
+        let mut cash = it.cash();
+        let navs = it.navs();
+        let price = navs.row(navs.shape()[0] - 1).to_vec();
+        for (j, config) in configs.iter().enumerate() {
+            let nav = it.nav(j)?;
+            // Sell lots whose selling level is reached.
+            while let Some(lot) = lots[j].last() {
+                let k = lots[j].len() - 1;
+                if price[j] < lot.nav + config.step_size() {
+                    break;
+                }
+                let fee = lot.shares * nav * config.fee_rate;
+                let income = lot.shares * nav - fee;
+                cash += income;
+                it.sell_comment(
+                    j,
+                    lot.shares,
+                    fee,
+                    &format!(
+                        "grid sell lot {} bought on {} at {:.4}, profit {:.2}",
+                        k,
+                        lot.date,
+                        lot.nav,
+                        income - lot.cost
+                    ),
+                )?;
+                lots[j].pop();
+                last_fill[j] = Some(nav);
+            }
+            // Buy a lot if its buying level is reached.
+            let level = config.buy_level(last_fill[j]);
+            if lots[j].len() < config.max_layers && price[j] <= level && cash >= config.amount {
+                let k = lots[j].len();
+                let fee = config.amount * config.fee_rate;
+                cash -= config.amount;
+                it.buy_comment(
+                    j,
+                    config.amount,
+                    fee,
+                    &format!("grid buy lot {} at level {:.4}", k, level),
+                )?;
+                lots[j].push(Lot {
+                    date: it.today(),
+                    nav,
+                    shares: (config.amount - fee) / nav,
+                    cost: config.amount,
+                });
+                last_fill[j] = Some(nav);
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::record::RecordSlice;
+    use crate::*;
+
+    #[test]
+    fn test_grid_1() {
+        let start_date = NaiveDate::parse_from_str("20240101", "%Y%m%d").unwrap();
+        let prices = [
+            1.0, 1.0, 0.95, 0.95, 0.9, 0.9, 0.95, 0.95, 1.0, 1.0, 1.05, 1.05,
+        ];
+        let mut fund = Fund::new("etf", "000001");
+        for (i, p) in prices.iter().enumerate() {
+            fund.append(start_date + Duration::days(i as i64), *p);
+        }
+        let trans = Transaction::new(&[&fund], None, None);
+        let mut it = trans.iter(false, true);
+        it.inflow(250.).unwrap();
+        let config = GridConfig {
+            base_price: 1.0,
+            step: GridStep::Percent(0.05),
+            max_layers: 2,
+            amount: 100.,
+            fee_rate: 0.,
+        };
+        grid(&mut it, &[config]).unwrap();
+        let record = it.fund_record(0).unwrap();
+        let comments: Vec<_> = record
+            .records()
+            .iter()
+            .map(|rs| rs.comment())
+            .filter(|c| !c.is_empty())
+            .collect();
+        assert_eq!(comments.len(), 4);
+        assert!(comments[0].starts_with("grid buy lot 0"));
+        assert!(comments[1].starts_with("grid buy lot 1"));
+        assert!(comments[2].starts_with("grid sell lot 1"));
+        assert!(comments[2].ends_with(&format!("profit {:.2}", 100. / 0.95 - 100.)));
+        assert!(comments[3].ends_with("profit 5.00"));
+        assert!(comments[3].starts_with("grid sell lot 0"));
+        assert_eq!(it.share(0), 0.);
+        assert!((it.cash() - (50. + 100. / 0.95 + 105.)).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_grid_gap() {
+        // The price gaps down by four steps and recovers by two.
+        let start_date = NaiveDate::parse_from_str("20240101", "%Y%m%d").unwrap();
+        let prices = [1.0, 1.0, 0.8, 0.8, 0.8, 0.9, 0.9];
+        let mut fund = Fund::new("etf", "000001");
+        for (i, p) in prices.iter().enumerate() {
+            fund.append(start_date + Duration::days(i as i64), *p);
+        }
+        let trans = Transaction::new(&[&fund], None, None);
+        let mut it = trans.iter(false, true);
+        it.inflow(1000.).unwrap();
+        let config = GridConfig {
+            base_price: 1.0,
+            step: GridStep::Absolute(0.05),
+            max_layers: 5,
+            amount: 100.,
+            fee_rate: 0.,
+        };
+        grid(&mut it, &[config]).unwrap();
+        let record = it.fund_record(0).unwrap();
+        let comments: Vec<_> = record
+            .records()
+            .iter()
+            .map(|rs| rs.comment())
+            .filter(|c| !c.is_empty())
+            .collect();
+        // One lot is bought on the gap, which is sold one step above
+        // its fill of 0.8. The next lot is one step below the sale.
+        assert_eq!(comments.len(), 3);
+        assert_eq!(comments[0], "grid buy lot 0 at level 1.0000");
+        assert_eq!(comments[1], "grid buy lot 1 at level 0.9500");
+        assert!(comments[2].starts_with("grid sell lot 1"));
+        assert!(comments[2].ends_with("profit 12.50"));
+        assert!((it.share(0) - 100.).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_grid_2() {
+        let hs300 = Fund::from(&read_gta("hs300.txt").unwrap());
+        let start_date = NaiveDate::parse_from_str("20210101", "%Y%m%d").unwrap();
+        let end_date = NaiveDate::parse_from_str("20240101", "%Y%m%d").unwrap();
+        let trans = Transaction::new(&[&hs300], Some(start_date), Some(end_date));
+        let mut it = trans.iter(true, true);
+        it.inflow(10000.).unwrap();
+        let config = GridConfig {
+            base_price: 5000.,
+            step: GridStep::Absolute(200.),
+            max_layers: 10,
+            amount: 1000.,
+            fee_rate: 0.001,
+        };
+        grid(&mut it, &[config]).unwrap();
+        assert!(it.cash_log().unwrap().iter().all(|&x| x >= 0.));
+        let record = it.fund_record(0).unwrap();
+        let nsell = record
+            .records()
+            .iter()
+            .filter(|rs| rs.comment().contains("grid sell"))
+            .count();
+        assert!(nsell > 0);
+        assert!(record.records().iter().all(|rs| rs.total_share() >= -1e-9));
+    }
+}
@@ -1,5 +1,6 @@
+use crate::utility::quantile;
 use crate::DAYS_PER_YEAR;
-use crate::{TransactionIterator, Weekday};
+use crate::{Duration, Schedule, TransactionIterator, Weekday};
 use chrono::Datelike;
 use ndarray::{s, Array, Array1};
 
@@ -85,7 +86,7 @@ pub fn kelly_hint(
     // Get winning rate.
     let y_weekly = y
         .iter()
-        .zip(&it.dates()[it.ndays() - n..])
+        .zip(&it.dates()[it.dates().len() - n..])
         .filter(|(_yi, &di)| di.weekday() == weekday)
         .map(|(&yi, _di)| yi)
         .collect::<Array1<_>>();
@@ -175,6 +176,140 @@ pub fn kelly_weekly(
     Ok(())
 }
 
+/// Estimator of the payoff of Kelly strategy.
+///
+/// The expected income when win and the expected loss are estimated
+/// by the upper and lower values of the inflation adjusted NAV in
+/// history.
+#[derive(Debug, Clone, Copy)]
+pub enum Payoff {
+    /// Use the maximal and minimal values, as `kelly_weekly` does.
+    Extreme,
+    /// Use the `1 - q` and `q` quantiles, which are less sensitive to
+    /// outliers. `Quantile(0.)` is the same as `Extreme`.
+    Quantile(f64),
+}
+
+/// Parameters of generalized Kelly strategy for one fund.
+#[derive(Debug, Clone)]
+pub struct KellyConfig {
+    /// Sampling of NAVs to estimate the winning rate.
+    pub sampling: Schedule,
+    /// Length of history to use in calendar time.
+    pub lookback: Duration,
+    /// Annual inflation, applied by the calendar days to the last
+    /// NAV.
+    pub inflation: f64,
+    /// The bound for controlling risk.
+    pub risk_bound: f64,
+    pub payoff: Payoff,
+}
+
+/// Generalized version of `kelly_hint`.
+///
+/// NAVs within `config.lookback` before today are used. The winning
+/// rate is estimated from the NAVs sampled by `config.sampling`, and
+/// the payoff is estimated by `config.payoff`. Risk bounds are always
+/// given by the extreme values of the NAVs without inflation.
+pub fn kelly_general_hint(
+    it: &TransactionIterator,
+    fund_index: usize,
+    config: &KellyConfig,
+) -> Result<KellyIndicator, Box<dyn std::error::Error>> {
+    let dates = it.dates();
+    let start_date = it.today() - config.lookback;
+    if dates.is_empty() || dates[0] > start_date {
+        return Err(Box::new(KellyError("lookback too long for kelly strategy")));
+    }
+    let start = dates.partition_point(|&d| d < start_date);
+    let dates = &dates[start..];
+    let last_date = *dates.last().unwrap();
+    let navs = it.navs();
+    let y0 = navs.slice(s![start.., fund_index]);
+    // Net asset value considering inflation by calendar days.
+    let y = y0
+        .iter()
+        .zip(dates)
+        .map(|(&yi, &di)| {
+            let days = (last_date - di).num_days() as f64;
+            yi * (1. + config.inflation).powf(days / DAYS_PER_YEAR)
+        })
+        .collect::<Array1<_>>();
+    // Get winning rate.
+    let y_sampled = config
+        .sampling
+        .select(dates)
+        .into_iter()
+        .map(|i| y[i])
+        .collect::<Array1<_>>();
+    if y_sampled.len() < 2 {
+        return Err(Box::new(KellyError("too few samples for kelly strategy")));
+    }
+    let dy = &y_sampled.slice(s![1..]) - &y_sampled.slice(s![..-1]);
+    let p = dy.iter().filter(|&&x| x > 0.).count() as f64 / dy.len() as f64;
+    let q = 1. - p;
+    let (y_max, y_min) = match config.payoff {
+        Payoff::Extreme => maxmin!(y),
+        Payoff::Quantile(x) => {
+            let y = y.as_slice().unwrap();
+            (quantile(y, 1. - x), quantile(y, x))
+        }
+    };
+    let (y0_max, y0_min) = maxmin!(y0);
+
+    // Kelly.
+    let position = get_kelly_position(*y.last().unwrap(), y_max, y_min, p);
+    // Risk control.
+    let position = risk_control(
+        position,
+        *y0.last().unwrap(),
+        y0_max,
+        y0_min,
+        config.risk_bound,
+    );
+
+    let upper_bound = y_max * p + y_min * q;
+    let lower_bound = y_max * y_min / (y_max * q + y_min * p);
+    let bound_width = (y0_max - y0_min) * config.risk_bound;
+    let upper_risk_bound = y0_max - bound_width;
+    let lower_risk_bound = y0_min + bound_width;
+
+    Ok(KellyIndicator {
+        position,
+        upper_bound,
+        lower_bound,
+        upper_risk_bound,
+        lower_risk_bound,
+    })
+}
+
+/// Generalized version of `kelly_weekly`.
+///
+/// Transacts at each date of `schedule`, and the position of each fund
+/// is given by `kelly_general_hint` with its config.
+pub fn kelly_general(
+    it: &mut TransactionIterator,
+    schedule: Schedule,
+    configs: &[KellyConfig],
+) -> Result<(), Box<dyn std::error::Error>> {
+    if configs.len() != it.nfunds() {
+        return Err(Box::new(KellyError(
+            "number of configs does not match number of funds",
+        )));
+    }
+    while it.next_schedule(schedule).is_some() {
+        for (j, config) in configs.iter().enumerate() {
+            let f = kelly_general_hint(it, j, config)?.position;
+            // Adjust position
+            let total = it.asset() / it.nfunds() as f64 * f;
+            let current = it.fund_asset(j);
+            let amount = total - current;
+            it.buy_comment(j, amount, 0.0, &format!("position = {:.2}%", 100. * f))?;
+        }
+    }
+    Ok(())
+}
+
 /// Calculate the position given by kelly startegy.
 ///
 /// The expected income when win is estimated by the current position
@@ -301,4 +436,86 @@ mod test {
         assert!((result[3] - 1.489728842992303).abs() < 1e-6);
         assert!((result[4] - 1.3982690518133718).abs() < 1e-6);
     }
+
+    /// Hints in the middle of iteration should only use the history
+    /// up to today, just like at the end of a truncated transaction.
+    #[test]
+    fn test_kelly_hint_mid_iteration() {
+        let hs300 = Fund::from(&read_gta("hs300.txt").unwrap());
+        let trans = Transaction::new(&[&hs300], None, None);
+        let date = NaiveDate::parse_from_str("20170101", "%Y%m%d").unwrap();
+        let mut it = trans.iter(false, false);
+        it.goto(date);
+        let truncated = Transaction::new(&[&hs300], None, Some(it.today()));
+        let mut expected = truncated.iter(false, false);
+        while expected.next_day().is_some() {}
+        assert_eq!(it.dates(), expected.dates());
+        for n in [250, 1000] {
+            let hint = kelly_hint(&it, 0, Weekday::Wed, n, 0.015, 0.01).unwrap();
+            let expected = kelly_hint(&expected, 0, Weekday::Wed, n, 0.015, 0.01).unwrap();
+            assert_eq!(hint.position, expected.position);
+            assert_eq!(hint.upper_bound, expected.upper_bound);
+            assert_eq!(hint.lower_bound, expected.lower_bound);
+        }
+    }
+
+    #[test]
+    fn test_kelly_general() {
+        let hs300 = Fund::from(&read_gta("hs300.txt").unwrap());
+        let gz2000 = Fund::from(&read_gta("gz2000.txt").unwrap());
+        let start_date = NaiveDate::parse_from_str("20170101", "%Y%m%d").unwrap();
+        let end_date = NaiveDate::parse_from_str("20240101", "%Y%m%d").unwrap();
+        let trans = Transaction::new(&[&hs300, &gz2000], None, Some(end_date));
+
+        // Same as `kelly_hint` when the history and inflation match.
+        let mut it = trans.iter(false, false);
+        it.goto(start_date);
+        let n = 1300;
+        let dates = it.dates();
+        let config = KellyConfig {
+            sampling: Schedule::Weekly(Weekday::Wed),
+            lookback: it.today() - dates[dates.len() - n],
+            inflation: 0.,
+            risk_bound: 0.01,
+            payoff: Payoff::Extreme,
+        };
+        for j in 0..2 {
+            let expected = kelly_hint(&it, j, Weekday::Wed, n, 0., 0.01).unwrap();
+            let hint = kelly_general_hint(&it, j, &config).unwrap();
+            assert_eq!(hint.position, expected.position);
+            assert_eq!(hint.upper_bound, expected.upper_bound);
+            assert_eq!(hint.lower_bound, expected.lower_bound);
+            let config = KellyConfig {
+                payoff: Payoff::Quantile(0.),
+                ..config.clone()
+            };
+            let hint = kelly_general_hint(&it, j, &config).unwrap();
+            assert!((hint.position - expected.position).abs() < 1e-12);
+        }
+
+        for sampling in [
+            Schedule::Daily,
+            Schedule::Weekly(Weekday::Fri),
+            Schedule::Monthly(1),
+        ] {
+            let config = KellyConfig {
+                sampling,
+                lookback: Duration::days(5 * 365),
+                inflation: 0.015,
+                risk_bound: 0.01,
+                payoff: Payoff::Quantile(0.05),
+            };
+            let mut it = trans.iter(false, false);
+            it.goto(start_date);
+            it.inflow(1.).unwrap();
+            kelly_general(
+                &mut it,
+                Schedule::Weekly(Weekday::Fri),
+                &[config.clone(), config],
+            )
+            .unwrap();
+            assert!(it.cash() > -1e-9);
+            assert!(it.asset() > 0.5);
+        }
+    }
 }
@@ -0,0 +1,174 @@
+use crate::strategy::rebalance::rebalance_to_weights;
+use crate::utility::{covariance, maximize_quadratic, simple_returns};
+use crate::{TransactionIterator, Weekday};
+use chrono::Datelike;
+use ndarray::{s, Array1, Array2, Axis};
+
+#[derive(Debug)]
+pub struct KellyMultiError(&'static str);
+
+impl std::fmt::Display for KellyMultiError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        writeln!(f, "KellyMultiError: {}", self.0)
+    }
+}
+
+impl std::error::Error for KellyMultiError {}
+
+pub struct KellyMultiIndicator {
+    /// Weights of each fund in the total asset.
+    pub weights: Array1<f64>,
+    /// Mean of weekly returns of each fund.
+    pub mean: Array1<f64>,
+    /// Covariance matrix of weekly returns.
+    pub covariance: Array2<f64>,
+}
+
+/// Get the weights of all funds using multi-asset Kelly strategy.
+///
+/// Weekly returns of the funds are sampled on `weekday` from the NAVs
+/// of the last `n` days. The weights maximize the expected logarithm
+/// growth under the continuous approximation, which is
+/// `w' mean - w' covariance w / 2`, subject to `w >= 0` and
+/// `sum(w) <= 1`. Unlike `kelly_hint`, correlations between funds are
+/// considered, so correlated funds share the position instead of
+/// being allocated separately.
+///
+/// # Arguments
+///
+/// * `weekday` - Day of week to sample the returns.
+/// * `n` - Number of days of history to use.
+/// * `fraction` - Fractional Kelly, which scales the optimal weights.
+///   Use 1 for full Kelly.
+pub fn kelly_multi_hint(
+    it: &TransactionIterator,
+    weekday: Weekday,
+    n: usize,
+    fraction: f64,
+) -> Result<KellyMultiIndicator, Box<dyn std::error::Error>> {
+    if it.navs().shape()[0] < n {
+        return Err(Box::new(KellyMultiError("n too large for kelly strategy")));
+    }
+    let navs = it.navs();
+    let navs = navs.slice(s![-(n as isize).., ..]);
+    let rows: Vec<usize> = it.dates()[it.dates().len() - n..]
+        .iter()
+        .enumerate()
+        .filter(|(_, d)| d.weekday() == weekday)
+        .map(|(i, _)| i)
+        .collect();
+    if rows.len() < 3 {
+        return Err(Box::new(KellyMultiError(
+            "too few samples for kelly strategy",
+        )));
+    }
+    let y = navs.select(Axis(0), &rows);
+    let (mean, covariance) = covariance(simple_returns(y.view()).view());
+    let weights =
+        maximize_quadratic(mean.view(), covariance.view(), false, 1e-10, 100000) * fraction;
+    Ok(KellyMultiIndicator {
+        weights,
+        mean,
+        covariance,
+    })
+}
+
+/// The multi-asset Kelly strategy transacts weekly.
+///
+/// At each `weekday`, all the funds are rebalanced to the weights
+/// given by `kelly_multi_hint`, and the rest of the asset is kept as
+/// cash.
+///
+/// # Arguments
+///
+/// * `weekday` - Day of week to transact.
+/// * `n` - Number of days of history to use.
+/// * `fraction` - Fractional Kelly, which scales the optimal weights.
+/// * `fee_rates` - Fee as a fraction of the amount of each
+///   transaction for each fund.
+pub fn kelly_multi_weekly(
+    it: &mut TransactionIterator,
+    weekday: Weekday,
+    n: usize,
+    fraction: f64,
+    fee_rates: &[f64],
+) -> Result<(), Box<dyn std::error::Error>> {
+    if it.navs().shape()[0] < n {
+        return Err(Box::new(KellyMultiError(
+            "n too large for transaction simulation",
+        )));
+    }
+    if !(0. ..=1.).contains(&fraction) {
+        return Err(Box::new(KellyMultiError("fraction should be within 0..=1")));
+    }
+    while it.next_weekday(Some(weekday)).is_some() {
+        let indicator = kelly_multi_hint(it, weekday, n, fraction)?;
+        let comment = format!(
+            "positions = [{}]",
+            indicator
+                .weights
+                .iter()
+                .map(|w| format!("{:.2}%", 100. * w))
+                .collect::<Vec<_>>()
+                .join(", ")
+        );
+        rebalance_to_weights(
+            it,
+            indicator.weights.as_slice().unwrap(),
+            fee_rates,
+            &comment,
+        )?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::*;
+
+    #[test]
+    fn test_kelly_multi_hint() {
+        let hs300 = Fund::from(&read_gta("hs300.txt").unwrap());
+        let gz2000 = Fund::from(&read_gta("gz2000.txt").unwrap());
+        let start_date = NaiveDate::parse_from_str("20210101", "%Y%m%d").unwrap();
+        let trans = Transaction::new(&[&hs300, &gz2000], None, None);
+        let mut it = trans.iter(false, false);
+        it.goto(start_date);
+        let full = kelly_multi_hint(&it, Weekday::Fri, 1300, 1.).unwrap();
+        assert!(full.weights.iter().all(|&w| w >= 0.));
+        assert!(full.weights.sum() <= 1. + 1e-9);
+        // Funds are highly correlated.
+        let corr =
+            full.covariance[[0, 1]] / (full.covariance[[0, 0]] * full.covariance[[1, 1]]).sqrt();
+        assert!(corr > 0.5);
+        // Fractional Kelly scales the weights.
+        let half = kelly_multi_hint(&it, Weekday::Fri, 1300, 0.5).unwrap();
+        assert!((&full.weights * 0.5 - &half.weights)
+            .iter()
+            .all(|d| d.abs() < 1e-9));
+        // Single fund agrees with the closed form mean / variance.
+        let trans = Transaction::new(&[&hs300], None, None);
+        let mut it = trans.iter(false, false);
+        it.goto(start_date);
+        let single = kelly_multi_hint(&it, Weekday::Fri, 1300, 1.).unwrap();
+        let expected = (single.mean[0] / single.covariance[[0, 0]]).clamp(0., 1.);
+        assert!((single.weights[0] - expected).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_kelly_multi_weekly() {
+        let hs300 = Fund::from(&read_gta("hs300.txt").unwrap());
+        let gz2000 = Fund::from(&read_gta("gz2000.txt").unwrap());
+        let start_date = NaiveDate::parse_from_str("20170101", "%Y%m%d").unwrap();
+        let end_date = NaiveDate::parse_from_str("20240101", "%Y%m%d").unwrap();
+        let trans = Transaction::new(&[&hs300, &gz2000], None, Some(end_date));
+        let mut it = trans.iter(true, false);
+        it.goto(start_date);
+        it.inflow(1.).unwrap();
+        kelly_multi_weekly(&mut it, Weekday::Fri, 1300, 1., &[0.001; 2]).unwrap();
+        // The weights sum to no more than 1, so no cash is borrowed.
+        assert!(it.cash_log().unwrap().iter().all(|&c| c > -1e-9));
+        assert!(it.asset() > 0.);
+    }
+}
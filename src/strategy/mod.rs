@@ -1,5 +1,29 @@
 pub mod aip;
+pub mod aip_ma;
+pub mod aip_valuation;
+pub mod allocation;
+pub mod glide;
+pub mod grid;
 pub mod kelly;
+pub mod kelly_multi;
+pub mod rebalance;
+pub mod risk;
+pub mod rotation;
+pub mod trend;
+pub mod value_averaging;
+pub mod vol_target;
 
-pub use kelly::{kelly_weekly, kelly_hint};
+pub use kelly::{kelly_weekly, kelly_hint, kelly_general, kelly_general_hint};
+pub use kelly_multi::{kelly_multi_hint, kelly_multi_weekly};
 pub use aip::aip_monthly;
+pub use aip_ma::aip_ma_deviation;
+pub use aip_valuation::aip_valuation;
+pub use allocation::{allocation_hint, allocation_schedule};
+pub use glide::{glide_path, GlidePath};
+pub use grid::grid;
+pub use rebalance::{rebalance_band, rebalance_to_weights};
+pub use risk::{RiskOverlay, RiskRules, RiskTrigger};
+pub use rotation::rotation;
+pub use trend::trend_following;
+pub use value_averaging::value_averaging;
+pub use vol_target::{vol_target, vol_target_weights};
@@ -0,0 +1,275 @@
+use crate::{Schedule, TransactionIterator};
+
+#[derive(Debug)]
+pub struct RebalanceError(&'static str);
+
+impl std::fmt::Display for RebalanceError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        writeln!(f, "RebalanceError: {}", self.0)
+    }
+}
+
+impl std::error::Error for RebalanceError {}
+
+/// Width of the band around the target weight.
+#[derive(Debug, Clone, Copy)]
+pub enum Band {
+    /// Maximal difference between the weight and the target weight,
+    /// e.g. 0.05 allows a target of 60% to drift within 55%..65%.
+    Absolute(f64),
+    /// Maximal difference as a fraction of the target weight, e.g.
+    /// 0.2 allows a target of 60% to drift within 48%..72%.
+    Relative(f64),
+}
+
+impl Band {
+    fn exceeded(&self, weight: f64, target: f64) -> bool {
+        let width = match *self {
+            Band::Absolute(x) => x,
+            Band::Relative(x) => x * target,
+        };
+        (weight - target).abs() > width
+    }
+}
+
+/// Summary of the rebalances made by `rebalance_band`.
+#[derive(Debug, Clone, Default)]
+pub struct RebalanceSummary {
+    /// Number of rebalances, including the initial allocation.
+    pub count: usize,
+    /// Total amount of money bought and sold.
+    pub turnover: f64,
+}
+
+/// Adjust the positions of all funds to the given weights.
+///
+/// Weights are fractions of the total asset at the beginning of the
+/// day, and the rest is kept as cash. Overweight funds are sold
+/// before underweight funds are bought. Positions are estimated by
+/// the NAVs of the previous day, so the weights after the transaction
+/// deviate slightly from the targets. Purchases are scaled down if
+/// the cash and the income of selling are not enough, unless the
+/// weights sum to more than 1, where the shortfall is borrowed as
+/// negative cash. Returns the amount of money bought and sold.
+///
+/// # Arguments
+///
+/// * `weights` - Target weights of each fund.
+/// * `fee_rates` - Fee as a fraction of the amount of each
+///   transaction for each fund.
+/// * `comment` - Comment of the transactions.
+pub fn rebalance_to_weights(
+    it: &mut TransactionIterator,
+    weights: &[f64],
+    fee_rates: &[f64],
+    comment: &str,
+) -> Result<f64, Box<dyn std::error::Error>> {
+    if weights.len() != it.nfunds() || fee_rates.len() != it.nfunds() {
+        return Err(Box::new(RebalanceError(
+            "number of weights or fee rates does not match number of funds",
+        )));
+    }
+    let navs = it.navs();
+    if navs.shape()[0] == 0 {
+        return Err(Box::new(RebalanceError(
+            "cannot rebalance on the first day",
+        )));
+    }
+    let price = navs.row(navs.shape()[0] - 1).to_vec();
+    let asset = it.asset();
+    let diffs: Vec<f64> = (0..it.nfunds())
+        .map(|j| asset * weights[j] - it.fund_asset(j))
+        .collect();
+    let mut turnover = 0.;
+    let mut available = it.cash();
+    for (j, &diff) in diffs.iter().enumerate() {
+        if diff < 0. {
+            let share = f64::min(-diff / price[j], it.share(j));
+            let amount = share * it.nav(j)?;
+            turnover += amount;
+            available += amount * (1. - fee_rates[j]);
+            it.sell_comment(j, share, amount * fee_rates[j], comment)?;
+        }
+    }
+    let total: f64 = diffs.iter().filter(|&&x| x > 0.).sum();
+    let leveraged = weights.iter().sum::<f64>() > 1. + 1e-9;
+    let scale = if total > available && !leveraged {
+        f64::max(available, 0.) / total
+    } else {
+        1.
+    };
+    for (j, &diff) in diffs.iter().enumerate() {
+        if diff > 0. {
+            let amount = diff * scale;
+            turnover += amount;
+            it.buy_comment(j, amount, amount * fee_rates[j], comment)?;
+        }
+    }
+    Ok(turnover)
+}
+
+/// Rebalance fixed target weights when they drift out of the band.
+///
+/// The weights of the funds are checked at each date of `schedule`.
+/// If the weight of any fund is out of its band, all the funds are
+/// rebalanced to the target weights by `rebalance_to_weights`. The
+/// initial allocation is made at the first check where no fund is
+/// held. Cash should be provided by `inflow` before calling this
+/// function.
+///
+/// # Arguments
+///
+/// * `schedule` - Schedule of checking the weights.
+/// * `weights` - Target weights of each fund, whose sum should not
+///   exceed 1. The rest is kept as cash.
+/// * `bands` - Band of each fund.
+/// * `fee_rates` - Fee as a fraction of the amount of each
+///   transaction for each fund.
+pub fn rebalance_band(
+    it: &mut TransactionIterator,
+    schedule: Schedule,
+    weights: &[f64],
+    bands: &[Band],
+    fee_rates: &[f64],
+) -> Result<RebalanceSummary, Box<dyn std::error::Error>> {
+    if weights.len() != it.nfunds() || bands.len() != it.nfunds() {
+        return Err(Box::new(RebalanceError(
+            "number of weights or bands does not match number of funds",
+        )));
+    }
+    if weights.iter().any(|&w| w < 0.) || weights.iter().sum::<f64>() > 1. + 1e-9 {
+        return Err(Box::new(RebalanceError(
+            "weights should be non-negative and sum to no more than 1",
+        )));
+    }
+    let mut summary = RebalanceSummary::default();
+    while it.next_schedule(schedule).is_some() {
+        let asset = it.asset();
+        if asset <= 0. {
+            continue;
+        }
+        let comment = if (0..it.nfunds()).all(|j| it.share(j) == 0.) {
+            "initial allocation".to_string()
+        } else if let Some(j) =
+            (0..it.nfunds()).find(|&j| bands[j].exceeded(it.fund_asset(j) / asset, weights[j]))
+        {
+            format!(
+                "rebalance, weight of {} at {:.2}%",
+                it.transaction().names()[j],
+                100. * it.fund_asset(j) / asset
+            )
+        } else {
+            continue;
+        };
+        summary.turnover += rebalance_to_weights(it, weights, fee_rates, &comment)?;
+        summary.count += 1;
+    }
+    Ok(summary)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::*;
+    use chrono::Datelike;
+
+    #[test]
+    fn test_rebalance_band_1() {
+        let hs300 = Fund::from(&read_gta("hs300.txt").unwrap());
+        let gz2000 = Fund::from(&read_gta("gz2000.txt").unwrap());
+        let start_date = NaiveDate::parse_from_str("20110101", "%Y%m%d").unwrap();
+        let end_date = NaiveDate::parse_from_str("20240101", "%Y%m%d").unwrap();
+        let trans = Transaction::new(&[&hs300, &gz2000], Some(start_date), Some(end_date));
+        let weights = [0.6, 0.4];
+
+        // Zero band rebalances at every check.
+        let mut it = trans.iter(false, false);
+        it.inflow(10000.).unwrap();
+        let summary = rebalance_band(
+            &mut it,
+            Schedule::Monthly(1),
+            &weights,
+            &[Band::Absolute(0.); 2],
+            &[0.; 2],
+        )
+        .unwrap();
+        let mut it = trans.iter(false, false);
+        let mut nchecks = 0;
+        while it.next_schedule(Schedule::Monthly(1)).is_some() {
+            nchecks += 1;
+        }
+        assert_eq!(summary.count, nchecks);
+
+        // Infinite band only makes the initial allocation.
+        let mut it = trans.iter(false, false);
+        it.inflow(10000.).unwrap();
+        let summary = rebalance_band(
+            &mut it,
+            Schedule::Monthly(1),
+            &weights,
+            &[Band::Relative(f64::INFINITY); 2],
+            &[0.; 2],
+        )
+        .unwrap();
+        assert_eq!(summary.count, 1);
+        assert!((summary.turnover - 10000.).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_rebalance_band_2() {
+        let hs300 = Fund::from(&read_gta("hs300.txt").unwrap());
+        let gz2000 = Fund::from(&read_gta("gz2000.txt").unwrap());
+        let start_date = NaiveDate::parse_from_str("20110101", "%Y%m%d").unwrap();
+        let end_date = NaiveDate::parse_from_str("20240101", "%Y%m%d").unwrap();
+        let trans = Transaction::new(&[&hs300, &gz2000], Some(start_date), Some(end_date));
+        let weights = [0.6, 0.4];
+        let mut it = trans.iter(true, true);
+        it.inflow(10000.).unwrap();
+        let summary = rebalance_band(
+            &mut it,
+            Schedule::Weekly(Weekday::Mon),
+            &weights,
+            &[Band::Absolute(0.05); 2],
+            &[0.001; 2],
+        )
+        .unwrap();
+        assert!(summary.count > 1 && summary.count < 50);
+        assert!(summary.turnover > 10000.);
+        // Weights stay around the band on each week.
+        let asset = it.asset_log().unwrap();
+        let fund_asset = it.fund_asset_log(0).unwrap();
+        for (i, d) in it.dates().iter().enumerate().skip(1) {
+            if d.weekday() == Weekday::Mon {
+                assert!((fund_asset[i] / asset[i] - 0.6).abs() < 0.1);
+            }
+        }
+    }
+
+    /// Selling is settled at the NAV of today, which can be far from
+    /// the estimate by the previous NAV. Purchases should not spend
+    /// more than the actual income.
+    #[test]
+    fn test_rebalance_to_weights_cash() {
+        let date = |s| NaiveDate::parse_from_str(s, "%Y-%m-%d").unwrap();
+        let mut fund1 = Fund::new("fund1", "000001");
+        let mut fund2 = Fund::new("fund2", "000002");
+        for (d, nav1, nav2) in [
+            ("2024-01-01", 1.0, 1.0),
+            ("2024-01-02", 0.5, 1.0),
+            ("2024-01-03", 0.5, 1.0),
+        ] {
+            fund1.append(date(d), nav1);
+            fund2.append(date(d), nav2);
+        }
+        let trans = Transaction::new(&[&fund1, &fund2], None, None);
+        let mut it = trans.iter(false, false);
+        it.inflow(100.).unwrap();
+        it.buy(0, 100., 0.).unwrap();
+        it.next_day();
+        let turnover = rebalance_to_weights(&mut it, &[0., 1.], &[0.; 2], "").unwrap();
+        it.next_day();
+        assert!((turnover - 100.).abs() < 1e-9);
+        assert!(it.cash().abs() < 1e-9);
+        assert!((it.share(1) - 50.).abs() < 1e-9);
+    }
+}
@@ -0,0 +1,337 @@
+use crate::TransactionIterator;
+
+#[derive(Debug)]
+pub struct RiskError(&'static str);
+
+impl std::fmt::Display for RiskError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        writeln!(f, "RiskError: {}", self.0)
+    }
+}
+
+impl std::error::Error for RiskError {}
+
+/// Rules of `RiskOverlay`. Rules of `None` are disabled.
+#[derive(Debug, Clone, Default)]
+pub struct RiskRules {
+    /// Sell the fund when its NAV falls by this fraction from the
+    /// average cost, e.g. 0.1 for 10%.
+    pub stop_loss: Option<f64>,
+    /// Sell the fund when its NAV falls by this fraction from the
+    /// peak since entry.
+    pub trailing_stop: Option<f64>,
+    /// Sell the fund when its NAV rises by this fraction from the
+    /// average cost.
+    pub take_profit: Option<f64>,
+    /// Sell all the funds when the total asset falls by this fraction
+    /// from its peak.
+    pub max_drawdown: Option<f64>,
+    /// Number of days a fund should not be held after its rule is
+    /// triggered. For `max_drawdown`, it applies to all the funds.
+    pub cool_down: usize,
+}
+
+/// Rule triggered by `RiskOverlay`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RiskTrigger {
+    StopLoss(usize),
+    TrailingStop(usize),
+    TakeProfit(usize),
+    MaxDrawdown,
+    /// Shares bought during the cool-down period are sold.
+    CoolDown(usize),
+}
+
+/// Position of a fund tracked by `RiskOverlay`.
+#[derive(Debug, Clone, Default)]
+struct Position {
+    share: f64,
+    cost: f64,
+    peak: f64,
+    /// Index of the first day the fund can be held again.
+    blocked_until: usize,
+}
+
+/// Risk overlay that can be applied to any strategy.
+///
+/// The overlay should be applied at each step of the strategy before
+/// its transactions, for example:
+/// ```ignore
+/// let mut overlay = RiskOverlay::new(&it, rules, &fee_rates);
+/// while it.next_day().is_some() {
+///     overlay.apply(&mut it)?;
+///     for j in 0..it.nfunds() {
+///         if !overlay.is_blocked(&it, j) {
+///             // Transactions of the strategy.
+///         }
+///     }
+/// }
+/// ```
+/// It tracks the average cost and the peak NAV of each fund by the
+/// changes of shares between the steps, and sells all the shares of a
+/// fund when its rule is triggered. Decisions are made by the NAV of
+/// the previous day. Triggered rules are recorded in the comments of
+/// the funds.
+pub struct RiskOverlay {
+    rules: RiskRules,
+    fee_rates: Vec<f64>,
+    positions: Vec<Position>,
+    asset_peak: f64,
+    /// Index of the day of the last step.
+    last_index: Option<usize>,
+}
+
+impl RiskOverlay {
+    pub fn new(it: &TransactionIterator, rules: RiskRules, fee_rates: &[f64]) -> Self {
+        RiskOverlay {
+            rules,
+            fee_rates: fee_rates.to_vec(),
+            positions: vec![Position::default(); it.nfunds()],
+            asset_peak: 0.,
+            last_index: None,
+        }
+    }
+
+    /// Whether the fund is in its cool-down period.
+    pub fn is_blocked(&self, it: &TransactionIterator, idx: usize) -> bool {
+        it.dates().len() < self.positions[idx].blocked_until
+    }
+
+    /// Average cost of a fund per share, or `None` if it is not held.
+    pub fn average_cost(&self, idx: usize) -> Option<f64> {
+        let p = &self.positions[idx];
+        (p.share > 0.).then(|| p.cost / p.share)
+    }
+
+    /// Update the positions by the transactions since the last step.
+    fn update(&mut self, it: &TransactionIterator) {
+        let navs = it.navs();
+        let n = navs.shape()[0];
+        for (j, p) in self.positions.iter_mut().enumerate() {
+            let share = it.share(j);
+            if let Some(last) = self.last_index {
+                // Transactions of the last step are settled at its NAV.
+                let nav = navs[[last, j]];
+                if share > p.share {
+                    if p.share <= 0. {
+                        p.peak = nav;
+                    }
+                    p.cost += (share - p.share) * nav;
+                } else if share < p.share {
+                    p.cost *= share.max(0.) / p.share;
+                }
+                for t in last..n {
+                    p.peak = p.peak.max(navs[[t, j]]);
+                }
+            }
+            p.share = share;
+        }
+    }
+
+    /// Sell all the shares of a fund.
+    fn close(
+        &mut self,
+        it: &mut TransactionIterator,
+        idx: usize,
+        comment: &str,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let share = it.share(idx);
+        let fee = share * it.nav(idx)? * self.fee_rates[idx];
+        it.sell_comment(idx, share, fee, comment)?;
+        let p = &mut self.positions[idx];
+        p.blocked_until = it.dates().len() + self.rules.cool_down + 1;
+        Ok(())
+    }
+
+    /// Check the rules and sell the funds triggered.
+    ///
+    /// Returns the rules triggered at this step.
+    pub fn apply(
+        &mut self,
+        it: &mut TransactionIterator,
+    ) -> Result<Vec<RiskTrigger>, Box<dyn std::error::Error>> {
+        if self.fee_rates.len() != it.nfunds() {
+            return Err(Box::new(RiskError(
+                "number of fee rates does not match number of funds",
+            )));
+        }
+        let mut triggers = Vec::new();
+        let navs = it.navs();
+        let n = navs.shape()[0];
+        if n == 0 {
+            return Ok(triggers);
+        }
+        let price = navs.row(n - 1).to_vec();
+        self.update(it);
+        self.last_index = Some(n);
+
+        // Portfolio level circuit breaker.
+        let asset = it.asset();
+        self.asset_peak = self.asset_peak.max(asset);
+        if let Some(max_drawdown) = self.rules.max_drawdown {
+            let drawdown = 1. - asset / self.asset_peak;
+            if self.asset_peak > 0. && drawdown >= max_drawdown {
+                let held: Vec<usize> = (0..it.nfunds()).filter(|&j| it.share(j) > 0.).collect();
+                if !held.is_empty() {
+                    let comment = format!("circuit breaker: drawdown {:.2}%", 100. * drawdown);
+                    for j in held {
+                        self.close(it, j, &comment)?;
+                    }
+                    triggers.push(RiskTrigger::MaxDrawdown);
+                }
+                for p in self.positions.iter_mut() {
+                    p.blocked_until = it.dates().len() + self.rules.cool_down + 1;
+                }
+                self.asset_peak = asset;
+                return Ok(triggers);
+            }
+        }
+
+        for (j, &price) in price.iter().enumerate() {
+            let p = &self.positions[j];
+            if p.share <= 0. {
+                continue;
+            }
+            let cost = p.cost / p.share;
+            let trigger = if self.is_blocked(it, j) {
+                Some((RiskTrigger::CoolDown(j), "cool-down".to_string()))
+            } else if self
+                .rules
+                .stop_loss
+                .is_some_and(|x| price <= cost * (1. - x))
+            {
+                Some((
+                    RiskTrigger::StopLoss(j),
+                    format!("stop loss: nav {:.4}, cost {:.4}", price, cost),
+                ))
+            } else if self
+                .rules
+                .trailing_stop
+                .is_some_and(|x| price <= p.peak * (1. - x))
+            {
+                Some((
+                    RiskTrigger::TrailingStop(j),
+                    format!("trailing stop: nav {:.4}, peak {:.4}", price, p.peak),
+                ))
+            } else if self
+                .rules
+                .take_profit
+                .is_some_and(|x| price >= cost * (1. + x))
+            {
+                Some((
+                    RiskTrigger::TakeProfit(j),
+                    format!("take profit: nav {:.4}, cost {:.4}", price, cost),
+                ))
+            } else {
+                None
+            };
+            if let Some((trigger, comment)) = trigger {
+                let blocked_until = self.positions[j].blocked_until;
+                self.close(it, j, &comment)?;
+                if let RiskTrigger::CoolDown(_) = trigger {
+                    self.positions[j].blocked_until = blocked_until;
+                }
+                triggers.push(trigger);
+            }
+        }
+        Ok(triggers)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::record::RecordSlice;
+    use crate::*;
+
+    /// Buy all in whenever the fund is not held and not blocked.
+    fn run(trans: &Transaction, rules: RiskRules) -> (TransactionIterator<'_>, Vec<RiskTrigger>) {
+        let mut it = trans.iter(true, true);
+        it.inflow(10000.).unwrap();
+        let mut overlay = RiskOverlay::new(&it, rules, &[0.001]);
+        let mut triggers = Vec::new();
+        while it.next_day().is_some() {
+            triggers.extend(overlay.apply(&mut it).unwrap());
+            if it.share(0) == 0. && !overlay.is_blocked(&it, 0) && it.cash() > 0. {
+                let cash = it.cash();
+                it.buy_comment(0, cash, cash * 0.001, "buy").unwrap();
+            }
+        }
+        (it, triggers)
+    }
+
+    #[test]
+    fn test_risk_overlay_1() {
+        let hs300 = Fund::from(&read_gta("hs300.txt").unwrap());
+        let start_date = NaiveDate::parse_from_str("20150101", "%Y%m%d").unwrap();
+        let end_date = NaiveDate::parse_from_str("20170101", "%Y%m%d").unwrap();
+        let trans = Transaction::new(&[&hs300], Some(start_date), Some(end_date));
+
+        let rules = RiskRules {
+            stop_loss: Some(0.1),
+            cool_down: 20,
+            ..Default::default()
+        };
+        let (it, triggers) = run(&trans, rules);
+        assert!(triggers.contains(&RiskTrigger::StopLoss(0)));
+        assert!(triggers.iter().all(|t| *t == RiskTrigger::StopLoss(0)));
+        // No buy within cool-down after stop loss.
+        let record = it.fund_record(0).unwrap();
+        let slices = record.records();
+        for (i, rs) in slices.iter().enumerate() {
+            if rs.comment().starts_with("stop loss") {
+                let next_buy = slices[i + 1..].iter().find(|rs| rs.comment() == "buy");
+                if let Some(next_buy) = next_buy {
+                    assert!((next_buy.date() - rs.date()).num_days() >= 20);
+                }
+            }
+        }
+
+        let rules = RiskRules {
+            trailing_stop: Some(0.1),
+            ..Default::default()
+        };
+        let (_, triggers) = run(&trans, rules);
+        assert!(triggers.contains(&RiskTrigger::TrailingStop(0)));
+
+        let rules = RiskRules {
+            take_profit: Some(0.2),
+            ..Default::default()
+        };
+        let (it, triggers) = run(&trans, rules);
+        assert!(triggers.contains(&RiskTrigger::TakeProfit(0)));
+        assert!(it
+            .fund_record(0)
+            .unwrap()
+            .records()
+            .iter()
+            .any(|rs| rs.comment().starts_with("take profit")));
+    }
+
+    #[test]
+    fn test_risk_overlay_2() {
+        let hs300 = Fund::from(&read_gta("hs300.txt").unwrap());
+        let start_date = NaiveDate::parse_from_str("20150101", "%Y%m%d").unwrap();
+        let end_date = NaiveDate::parse_from_str("20170101", "%Y%m%d").unwrap();
+        let trans = Transaction::new(&[&hs300], Some(start_date), Some(end_date));
+        let rules = RiskRules {
+            max_drawdown: Some(0.15),
+            cool_down: 60,
+            ..Default::default()
+        };
+        let (it, triggers) = run(&trans, rules);
+        assert!(triggers.contains(&RiskTrigger::MaxDrawdown));
+        // Drawdown is limited around the bound.
+        let asset = it.asset_log().unwrap();
+        let mut peak: f64 = 0.;
+        for &a in asset.iter() {
+            peak = peak.max(a);
+            assert!(a / peak > 0.75);
+        }
+        let record = it.fund_record(0).unwrap();
+        assert!(record
+            .records()
+            .iter()
+            .any(|rs| rs.comment().starts_with("circuit breaker")));
+    }
+}
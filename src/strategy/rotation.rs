@@ -0,0 +1,236 @@
+use crate::strategy::allocation::inverse_volatility;
+use crate::strategy::rebalance::rebalance_to_weights;
+use crate::utility::simple_returns;
+use crate::TransactionIterator;
+use ndarray::{s, Axis};
+
+#[derive(Debug)]
+pub struct RotationError(&'static str);
+
+impl std::fmt::Display for RotationError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        writeln!(f, "RotationError: {}", self.0)
+    }
+}
+
+impl std::error::Error for RotationError {}
+
+/// Weighting of the selected funds.
+#[derive(Debug, Clone, Copy)]
+pub enum Weighting {
+    Equal,
+    /// Weights proportional to the inverse of the volatility of daily
+    /// returns of the last given days. The volatility is floored at
+    /// 0.01%, so a fund with flat NAVs, such as a money market fund,
+    /// takes almost all the weight.
+    InverseVolatility(usize),
+}
+
+/// Momentum rotation strategy.
+///
+/// At the given day of each month, funds are ranked by their return
+/// over `lookback` days, ending `skip` days before the previous day
+/// to exclude the short-term reversal. The top `k` funds are held by
+/// `weighting`, and the others are sold. If `threshold` is given, the
+/// selected funds whose return is not larger than it are replaced by
+/// cash, which is the absolute momentum filter. No transaction is
+/// made until there are enough NAVs in history. Cash should be
+/// provided by `inflow` before calling this function.
+///
+/// # Arguments
+///
+/// * `day` - Day of month to rotate.
+/// * `lookback` - Number of days to calculate the return.
+/// * `skip` - Number of recent days excluded from the return.
+/// * `k` - Number of funds to hold.
+/// * `threshold` - Minimal return to hold a fund, e.g. 0.
+/// * `weighting` - Weighting of the selected funds.
+/// * `fee_rates` - Fee as a fraction of the amount of each
+///   transaction for each fund.
+#[allow(clippy::too_many_arguments)]
+pub fn rotation(
+    it: &mut TransactionIterator,
+    day: u32,
+    lookback: usize,
+    skip: usize,
+    k: usize,
+    threshold: Option<f64>,
+    weighting: Weighting,
+    fee_rates: &[f64],
+) -> Result<(), Box<dyn std::error::Error>> {
+    if lookback == 0 || k == 0 {
+        return Err(Box::new(RotationError("lookback and k should be positive")));
+    }
+    let window = match weighting {
+        Weighting::Equal => 0,
+        Weighting::InverseVolatility(window) if window < 2 => {
+            return Err(Box::new(RotationError(
+                "volatility window should be at least 2",
+            )));
+        }
+        Weighting::InverseVolatility(window) => window,
+    };
+    let names = it.transaction().names();
+    while it.next_month(Some(day)).is_some() {
+        let navs = it.navs();
+        let n = navs.shape()[0];
+        if n <= usize::max(lookback + skip, window) {
+            continue;
+        }
+        let end = n - 1 - skip;
+        let momentum: Vec<f64> = (0..it.nfunds())
+            .map(|j| navs[[end, j]] / navs[[end - lookback, j]] - 1.)
+            .collect();
+        let mut ranking: Vec<usize> = (0..it.nfunds()).collect();
+        ranking.sort_by(|&a, &b| momentum[b].total_cmp(&momentum[a]));
+        ranking.truncate(k);
+
+        let scores: Vec<f64> = match weighting {
+            Weighting::Equal => vec![1. / ranking.len() as f64; ranking.len()],
+            Weighting::InverseVolatility(window) => {
+                let returns = simple_returns(navs.slice(s![n - window - 1.., ..]));
+                inverse_volatility(returns.select(Axis(1), &ranking).view()).to_vec()
+            }
+        };
+        let mut weights = vec![0.; it.nfunds()];
+        let mut selected = Vec::new();
+        for (&j, score) in ranking.iter().zip(scores) {
+            if threshold.is_none_or(|t| momentum[j] > t) {
+                weights[j] = score;
+                selected.push(format!("{} {:.2}%", names[j], 100. * momentum[j]));
+            }
+        }
+        let comment = if selected.is_empty() {
+            "rotate to cash".to_string()
+        } else {
+            format!("rotate to {}", selected.join(", "))
+        };
+        rebalance_to_weights(it, &weights, fee_rates, &comment)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::*;
+
+    fn funds() -> Vec<Fund> {
+        let hs300 = Fund::from(&read_gta("hs300.txt").unwrap());
+        let gz2000 = Fund::from(&read_gta("gz2000.txt").unwrap());
+        // A bond fund growing steadily by 3% per year.
+        let mut bond = Fund::new("bond", "000000");
+        let start_date = hs300[0].date();
+        for s in hs300.data() {
+            let days = (s.date() - start_date).num_days() as f64;
+            bond.append(s.date(), 1.03f64.powf(days / DAYS_PER_YEAR));
+        }
+        vec![hs300, gz2000, bond]
+    }
+
+    #[test]
+    fn test_rotation_1() {
+        let funds = funds();
+        let funds: Vec<&Fund> = funds.iter().collect();
+        let start_date = NaiveDate::parse_from_str("20110101", "%Y%m%d").unwrap();
+        let end_date = NaiveDate::parse_from_str("20240101", "%Y%m%d").unwrap();
+        let trans = Transaction::new(&funds, Some(start_date), Some(end_date));
+
+        // Hold only one fund at a time.
+        let mut it = trans.iter(true, true);
+        it.inflow(10000.).unwrap();
+        rotation(&mut it, 1, 120, 20, 1, None, Weighting::Equal, &[0.001; 3]).unwrap();
+        let shares: Vec<_> = (0..3).map(|j| it.share_log(j).unwrap()).collect();
+        for i in 0..it.ndays() {
+            let nheld = shares.iter().filter(|s| s[i] > 1e-9).count();
+            assert!(nheld <= 1);
+        }
+        assert!((0..3).all(|j| it
+            .fund_record(j)
+            .unwrap()
+            .records()
+            .iter()
+            .any(|rs| rs.share() > 0.)));
+
+        // Nothing passes an infinite threshold.
+        let mut it = trans.iter(false, false);
+        it.inflow(10000.).unwrap();
+        rotation(
+            &mut it,
+            1,
+            120,
+            0,
+            2,
+            Some(f64::INFINITY),
+            Weighting::InverseVolatility(60),
+            &[0.001; 3],
+        )
+        .unwrap();
+        assert_eq!(it.asset(), 10000.);
+    }
+
+    #[test]
+    fn test_rotation_2() {
+        let funds = funds();
+        let funds: Vec<&Fund> = funds.iter().collect();
+        let start_date = NaiveDate::parse_from_str("20110101", "%Y%m%d").unwrap();
+        let end_date = NaiveDate::parse_from_str("20240101", "%Y%m%d").unwrap();
+        let trans = Transaction::new(&funds, Some(start_date), Some(end_date));
+        let mut it = trans.iter(true, true);
+        it.inflow(10000.).unwrap();
+        rotation(
+            &mut it,
+            15,
+            250,
+            0,
+            2,
+            Some(0.),
+            Weighting::InverseVolatility(60),
+            &[0.; 3],
+        )
+        .unwrap();
+        assert!(it.cash_log().unwrap().iter().all(|&x| x > -1e-9));
+        // The bond fund has the smallest volatility, thus the largest
+        // weight whenever it is selected with a stock index.
+        let asset = it.asset_log().unwrap();
+        let bond = it.fund_asset_log(2).unwrap();
+        let stock = &it.fund_asset_log(0).unwrap() + &it.fund_asset_log(1).unwrap();
+        let mixed = (0..it.ndays())
+            .filter(|&i| bond[i] > 1e-6 * asset[i] && stock[i] > 1e-6 * asset[i])
+            .collect::<Vec<_>>();
+        assert!(!mixed.is_empty());
+        assert!(mixed
+            .iter()
+            .all(|&i| bond[i] > stock[i] && bond[i] <= asset[i]));
+    }
+
+    #[test]
+    fn test_rotation_flat_fund() {
+        let hs300 = Fund::from(&read_gta("hs300.txt").unwrap());
+        // A money market fund whose NAV never changes.
+        let mut cash = Fund::new("cash", "000001");
+        for s in hs300.data() {
+            cash.append(s.date(), 1.);
+        }
+        let start_date = NaiveDate::parse_from_str("20200101", "%Y%m%d").unwrap();
+        let trans = Transaction::new(&[&hs300, &cash], Some(start_date), None);
+        let mut it = trans.iter(true, false);
+        it.inflow(10000.).unwrap();
+        rotation(
+            &mut it,
+            1,
+            20,
+            0,
+            2,
+            None,
+            Weighting::InverseVolatility(20),
+            &[0.; 2],
+        )
+        .unwrap();
+        assert!(it.asset().is_finite());
+        assert!((0..2).all(|j| it.share(j).is_finite()));
+        let asset = it.asset_log().unwrap();
+        let held = it.fund_asset_log(1).unwrap();
+        assert!((0..it.ndays()).any(|i| held[i] > 0.9 * asset[i]));
+    }
+}
@@ -0,0 +1,463 @@
+use crate::utility::annual_volatility;
+use crate::TransactionIterator;
+use ndarray::{s, ArrayView1};
+use std::collections::VecDeque;
+
+#[derive(Debug)]
+pub struct TrendError(&'static str);
+
+impl std::fmt::Display for TrendError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        writeln!(f, "TrendError: {}", self.0)
+    }
+}
+
+impl std::error::Error for TrendError {}
+
+/// Type of moving average.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MaType {
+    Simple,
+    /// Exponential moving average with smoothing factor `2 / (n + 1)`,
+    /// which is initialized by the simple moving average of the first
+    /// `n` values.
+    Exponential,
+}
+
+/// Trend signal of a fund.
+#[derive(Debug, Clone, Copy)]
+pub enum TrendSignal {
+    /// Hold the fund when the fast moving average is above the slow
+    /// one.
+    Crossover {
+        fast: usize,
+        slow: usize,
+        ma_type: MaType,
+    },
+    /// Buy the fund when the NAV breaks above the maximum of the last
+    /// `entry` days, and sell it when the NAV breaks below the
+    /// minimum of the last `exit` days.
+    Donchian { entry: usize, exit: usize },
+}
+
+/// Position sizing when a trend is entered.
+#[derive(Debug, Clone, Copy)]
+pub enum Sizing {
+    /// Invest all the asset allocated to the fund.
+    AllIn,
+    /// Invest `target / volatility` of the asset allocated to the fund
+    /// and no more than all of it, where `volatility` is the annual
+    /// volatility of daily returns of the last `window` days.
+    VolatilityScaled { target: f64, window: usize },
+}
+
+/// Parameters of trend following for one fund.
+#[derive(Debug, Clone)]
+pub struct TrendConfig {
+    pub signal: TrendSignal,
+    pub sizing: Sizing,
+    /// Fee as a fraction of the amount of each transaction.
+    pub fee_rate: f64,
+}
+
+/// Moving average updated by one value each time.
+struct MovingAverage {
+    n: usize,
+    ma_type: MaType,
+    window: VecDeque<f64>,
+    sum: f64,
+    value: Option<f64>,
+}
+
+impl MovingAverage {
+    fn new(n: usize, ma_type: MaType) -> Self {
+        MovingAverage {
+            n,
+            ma_type,
+            window: VecDeque::with_capacity(n + 1),
+            sum: 0.,
+            value: None,
+        }
+    }
+
+    /// Add a value and return the moving average if available.
+    fn update(&mut self, x: f64) -> Option<f64> {
+        match (self.ma_type, self.value) {
+            (MaType::Exponential, Some(value)) => {
+                let alpha = 2. / (self.n as f64 + 1.);
+                self.value = Some(value + alpha * (x - value));
+            }
+            _ => {
+                self.window.push_back(x);
+                self.sum += x;
+                if self.window.len() > self.n {
+                    self.sum -= self.window.pop_front().unwrap();
+                }
+                if self.window.len() == self.n {
+                    self.value = Some(self.sum / self.n as f64);
+                }
+            }
+        }
+        self.value
+    }
+}
+
+/// State of the signal of a fund.
+enum SignalState {
+    /// Fast and slow moving averages, and the number of NAVs they have
+    /// seen.
+    Crossover(MovingAverage, MovingAverage, usize),
+    Donchian {
+        entry: usize,
+        exit: usize,
+    },
+}
+
+impl SignalState {
+    fn new(signal: &TrendSignal) -> Self {
+        match *signal {
+            TrendSignal::Crossover {
+                fast,
+                slow,
+                ma_type,
+            } => SignalState::Crossover(
+                MovingAverage::new(fast, ma_type),
+                MovingAverage::new(slow, ma_type),
+                0,
+            ),
+            TrendSignal::Donchian { entry, exit } => SignalState::Donchian { entry, exit },
+        }
+    }
+
+    /// Update the state by the NAVs of a fund until yesterday, and
+    /// return whether to hold the fund with its reason. `None` is
+    /// returned if the trend does not change.
+    ///
+    /// Moving averages are fed by the NAVs they have not seen, so they
+    /// are seeded by the whole history on the first call.
+    fn update(&mut self, navs: ArrayView1<f64>, holding: bool) -> Option<(bool, String)> {
+        let n = navs.len();
+        let last = navs[n - 1];
+        match self {
+            SignalState::Crossover(fast, slow, seen) => {
+                for &x in navs.slice(s![*seen..]) {
+                    fast.update(x);
+                    slow.update(x);
+                }
+                *seen = n;
+                let (fast, slow) = (fast.value?, slow.value?);
+                if (fast > slow) != holding {
+                    let relation = if fast > slow { ">" } else { "<=" };
+                    Some((
+                        fast > slow,
+                        format!("fast MA {:.4} {} slow MA {:.4}", fast, relation, slow),
+                    ))
+                } else {
+                    None
+                }
+            }
+            SignalState::Donchian { entry, exit } => {
+                if holding {
+                    if n <= *exit {
+                        return None;
+                    }
+                    let low = navs
+                        .slice(s![n - 1 - *exit..n - 1])
+                        .iter()
+                        .fold(f64::INFINITY, |a, &b| a.min(b));
+                    (last < low).then(|| (false, format!("break below {:.4}", low)))
+                } else {
+                    if n <= *entry {
+                        return None;
+                    }
+                    let high = navs
+                        .slice(s![n - 1 - *entry..n - 1])
+                        .iter()
+                        .fold(f64::NEG_INFINITY, |a, &b| a.max(b));
+                    (last > high).then(|| (true, format!("break above {:.4}", high)))
+                }
+            }
+        }
+    }
+}
+
+/// Get the fraction of allocated asset to invest by `sizing`.
+fn position(sizing: &Sizing, it: &TransactionIterator, idx: usize) -> f64 {
+    match *sizing {
+        Sizing::AllIn => 1.,
+        Sizing::VolatilityScaled { target, window } => {
+            let navs = it.navs();
+            let n = navs.shape()[0];
+            if n <= window || window < 2 {
+                return 0.;
+            }
+            let y = navs.slice(s![n - window - 1.., idx]);
+            let returns = &y.slice(s![1..]) / &y.slice(s![..-1]) - 1.;
+            let dates = it.dates();
+            let days = (dates[n - 1] - dates[n - window - 1]).num_days() as f64;
+            let volatility = annual_volatility(returns.view(), days);
+            if volatility > 0. {
+                f64::min(target / volatility, 1.)
+            } else {
+                1.
+            }
+        }
+    }
+}
+
+/// Trend following strategy.
+///
+/// Signals of each fund are checked daily by the NAVs until the
+/// previous day. The asset is divided equally for the funds, and when
+/// a trend is entered, the fund is bought by the fraction of its
+/// allocated asset given by `Sizing`. The position is held until the
+/// trend ends, when all the shares are sold. Cash should be provided
+/// by `inflow` before calling this function, and purchases are
+/// limited by the cash available.
+///
+/// Comments of the transactions show the signal and the position.
+pub fn trend_following(
+    it: &mut TransactionIterator,
+    configs: &[TrendConfig],
+) -> Result<(), Box<dyn std::error::Error>> {
+    if configs.len() != it.nfunds() {
+        return Err(Box::new(TrendError(
+            "number of configs does not match number of funds",
+        )));
+    }
+    for config in configs {
+        match config.signal {
+            TrendSignal::Crossover { fast, slow, .. } if fast == 0 || fast >= slow => {
+                return Err(Box::new(TrendError(
+                    "fast window should be positive and less than slow window",
+                )));
+            }
+            TrendSignal::Donchian { entry, exit } if entry == 0 || exit == 0 => {
+                return Err(Box::new(TrendError("channel window should be positive")));
+            }
+            _ => {}
+        }
+    }
+    let mut states: Vec<SignalState> = configs
+        .iter()
+        .map(|c| SignalState::new(&c.signal))
+        .collect();
+    while it.next_day().is_some() {
+        let mut available = it.cash();
+        let asset = it.asset();
+        for (j, config) in configs.iter().enumerate() {
+            let holding = it.share(j) > 0.;
+            let Some((hold, reason)) = states[j].update(it.navs().column(j), holding) else {
+                continue;
+            };
+            if hold {
+                let f = position(&config.sizing, it, j);
+                let amount = f64::min(asset / it.nfunds() as f64 * f, available);
+                if amount > 0. {
+                    available -= amount;
+                    it.buy_comment(
+                        j,
+                        amount,
+                        amount * config.fee_rate,
+                        &format!("{}, position = {:.2}%", reason, 100. * f),
+                    )?;
+                }
+            } else {
+                let share = it.share(j);
+                let amount = share * it.nav(j)?;
+                available += amount * (1. - config.fee_rate);
+                it.sell_comment(
+                    j,
+                    share,
+                    amount * config.fee_rate,
+                    &format!("{}, position = 0.00%", reason),
+                )?;
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::record::RecordSlice;
+    use crate::*;
+
+    #[test]
+    fn test_moving_average() {
+        let mut ma = MovingAverage::new(3, MaType::Simple);
+        let res: Vec<_> = [1., 2., 3., 4.].iter().map(|&x| ma.update(x)).collect();
+        assert_eq!(res, vec![None, None, Some(2.), Some(3.)]);
+        let mut ema = MovingAverage::new(3, MaType::Exponential);
+        let res: Vec<_> = [1., 2., 3., 5.].iter().map(|&x| ema.update(x)).collect();
+        assert_eq!(res, vec![None, None, Some(2.), Some(3.5)]);
+    }
+
+    #[test]
+    fn test_crossover_first_signal() {
+        // The slow MA is available from the 4th NAV, when the fast MA
+        // is already above it.
+        let start_date = NaiveDate::parse_from_str("20240101", "%Y%m%d").unwrap();
+        let mut fund = Fund::new("etf", "000001");
+        for (i, nav) in [0.5, 1., 1., 1., 1.1, 1.2, 1.3, 1.4].iter().enumerate() {
+            fund.append(start_date + Duration::days(i as i64), *nav);
+        }
+        let trans = Transaction::new(&[&fund], None, None);
+        let mut it = trans.iter(false, true);
+        it.inflow(100.).unwrap();
+        let configs = [TrendConfig {
+            signal: TrendSignal::Crossover {
+                fast: 2,
+                slow: 4,
+                ma_type: MaType::Simple,
+            },
+            sizing: Sizing::AllIn,
+            fee_rate: 0.,
+        }];
+        trend_following(&mut it, &configs).unwrap();
+        let record = it.fund_record(0).unwrap();
+        let first = record
+            .records()
+            .iter()
+            .find(|rs| !rs.comment().is_empty())
+            .unwrap();
+        assert_eq!(first.date(), start_date + Duration::days(4));
+        assert!(first
+            .comment()
+            .starts_with("fast MA 1.0000 > slow MA 0.8750"));
+    }
+
+    #[test]
+    fn test_crossover_after_goto() {
+        // NAV keeps rising, so the fast MA is above the slow one on
+        // the first day after `goto`.
+        let start_date = NaiveDate::parse_from_str("20240101", "%Y%m%d").unwrap();
+        let mut fund = Fund::new("etf", "000001");
+        for i in 0..30 {
+            fund.append(start_date + Duration::days(i), 1. + 0.01 * i as f64);
+        }
+        let trans = Transaction::new(&[&fund], None, None);
+        let mut it = trans.iter(false, true);
+        it.goto(start_date + Duration::days(20));
+        it.inflow(100.).unwrap();
+        let configs = [TrendConfig {
+            signal: TrendSignal::Crossover {
+                fast: 3,
+                slow: 10,
+                ma_type: MaType::Exponential,
+            },
+            sizing: Sizing::AllIn,
+            fee_rate: 0.,
+        }];
+        trend_following(&mut it, &configs).unwrap();
+        let record = it.fund_record(0).unwrap();
+        let first = record
+            .records()
+            .iter()
+            .find(|rs| !rs.comment().is_empty())
+            .unwrap();
+        assert_eq!(first.date(), start_date + Duration::days(21));
+        assert!(first.comment().starts_with("fast MA"));
+    }
+
+    #[test]
+    fn test_trend_following_1() {
+        // NAV rises for 30 days, then falls for 30 days.
+        let start_date = NaiveDate::parse_from_str("20240101", "%Y%m%d").unwrap();
+        let mut fund = Fund::new("etf", "000001");
+        for i in 0..60 {
+            let nav = if i < 30 {
+                1. + 0.01 * i as f64
+            } else {
+                1.58 - 0.01 * i as f64
+            };
+            fund.append(start_date + Duration::days(i), nav);
+        }
+        let trans = Transaction::new(&[&fund, &fund], None, None);
+        let mut it = trans.iter(false, true);
+        it.inflow(100.).unwrap();
+        let configs = [
+            TrendConfig {
+                signal: TrendSignal::Crossover {
+                    fast: 3,
+                    slow: 10,
+                    ma_type: MaType::Exponential,
+                },
+                sizing: Sizing::AllIn,
+                fee_rate: 0.,
+            },
+            TrendConfig {
+                signal: TrendSignal::Donchian { entry: 5, exit: 3 },
+                sizing: Sizing::AllIn,
+                fee_rate: 0.,
+            },
+        ];
+        trend_following(&mut it, &configs).unwrap();
+        for j in 0..2 {
+            let record = it.fund_record(j).unwrap();
+            let comments: Vec<_> = record
+                .records()
+                .iter()
+                .map(|rs| rs.comment())
+                .filter(|c| !c.is_empty())
+                .collect();
+            assert_eq!(comments.len(), 2);
+            assert!(comments[0].ends_with("position = 100.00%"));
+            assert!(comments[1].ends_with("position = 0.00%"));
+            assert_eq!(it.share(j), 0.);
+        }
+        // Both enter after the rise starts and exit after the fall starts.
+        assert!(it.cash() > 100.);
+    }
+
+    #[test]
+    fn test_trend_following_2() {
+        let hs300 = Fund::from(&read_gta("hs300.txt").unwrap());
+        let gz2000 = Fund::from(&read_gta("gz2000.txt").unwrap());
+        let start_date = NaiveDate::parse_from_str("20110101", "%Y%m%d").unwrap();
+        let end_date = NaiveDate::parse_from_str("20240101", "%Y%m%d").unwrap();
+        let trans = Transaction::new(&[&hs300, &gz2000], Some(start_date), Some(end_date));
+        let sizing = Sizing::VolatilityScaled {
+            target: 0.15,
+            window: 60,
+        };
+        let configs = [
+            TrendConfig {
+                signal: TrendSignal::Crossover {
+                    fast: 20,
+                    slow: 120,
+                    ma_type: MaType::Simple,
+                },
+                sizing,
+                fee_rate: 0.001,
+            },
+            TrendConfig {
+                signal: TrendSignal::Donchian {
+                    entry: 55,
+                    exit: 20,
+                },
+                sizing,
+                fee_rate: 0.001,
+            },
+        ];
+        let mut it = trans.iter(true, true);
+        it.inflow(10000.).unwrap();
+        trend_following(&mut it, &configs).unwrap();
+        assert!(it.cash_log().unwrap().iter().all(|&x| x > -1e-9));
+        for j in 0..2 {
+            let record = it.fund_record(j).unwrap();
+            let n = record
+                .records()
+                .iter()
+                .filter(|rs| rs.investment() > 0.)
+                .count();
+            assert!(n > 5);
+            // Positions are scaled down by volatility.
+            assert!(record
+                .records()
+                .iter()
+                .any(|rs| rs.investment() > 0. && !rs.comment().ends_with("100.00%")));
+        }
+    }
+}
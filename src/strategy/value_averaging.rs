@@ -0,0 +1,162 @@
+use crate::{Schedule, TransactionIterator, DAYS_PER_YEAR};
+
+/// Value averaging strategy.
+///
+/// The target value of each fund grows by `increments` every period
+/// and by `growth_rate` per year. At each date of `schedule`, the
+/// shortfall of the fund asset from the target is bought, and the
+/// excess is sold if `allow_sell` is true. Income of selling is kept
+/// as cash and used for purchases of later periods before new money
+/// flows in.
+///
+/// # Arguments
+///
+/// * `schedule` - Schedule of the periods.
+/// * `increments` - Increment of target value per period for each fund.
+/// * `growth_rate` - Annual growth rate of the target value.
+/// * `max_contribution` - Maximal new money of each period. If the
+///   shortfall exceeds it, purchases of all funds are scaled down.
+/// * `allow_sell` - Whether to sell the excess.
+/// * `fees` - Fees of each transaction for each fund.
+pub fn value_averaging(
+    it: &mut TransactionIterator,
+    schedule: Schedule,
+    increments: &[f64],
+    growth_rate: f64,
+    max_contribution: Option<f64>,
+    allow_sell: bool,
+    fees: &[f64],
+) -> Result<(), Box<dyn std::error::Error>> {
+    let mut targets = vec![0.; it.nfunds()];
+    let mut last_date: Option<chrono::NaiveDate> = None;
+    while it.next_schedule(schedule).is_some() {
+        let growth = match last_date {
+            Some(date) => {
+                let days = (it.today() - date).num_days() as f64;
+                (1. + growth_rate).powf(days / DAYS_PER_YEAR)
+            }
+            None => 1.,
+        };
+        last_date = Some(it.today());
+
+        let available = it.cash();
+        let mut shortfalls = vec![0.; it.nfunds()];
+        for j in 0..it.nfunds() {
+            targets[j] = targets[j] * growth + increments[j];
+            let diff = targets[j] - it.fund_asset(j);
+            if diff > 0. {
+                shortfalls[j] = diff;
+            } else if diff < 0. && allow_sell {
+                let nav = it.navs()[[it.navs().shape()[0] - 1, j]];
+                it.sell_comment(
+                    j,
+                    f64::min(-diff / nav, it.share(j)),
+                    fees[j],
+                    &format!("target {:.2}, sell excess", targets[j]),
+                )?;
+            }
+        }
+
+        let total: f64 = shortfalls.iter().sum();
+        let mut contribution = f64::max(total - available, 0.);
+        let mut scale = 1.;
+        if let Some(cap) = max_contribution {
+            if contribution > cap {
+                scale = (available.max(0.) + cap) / total;
+                contribution = cap;
+            }
+        }
+        it.inflow(contribution)?;
+        for (j, shortfall) in shortfalls.iter().enumerate() {
+            if *shortfall > 0. {
+                it.buy_comment(
+                    j,
+                    shortfall * scale,
+                    fees[j],
+                    &format!("target {:.2}", targets[j]),
+                )?;
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::record::RecordSlice;
+    use crate::strategy::aip_monthly;
+    use crate::*;
+
+    #[test]
+    fn test_value_averaging_1() {
+        let hs300 = Fund::from(&read_gta("hs300.txt").unwrap());
+        let gz2000 = Fund::from(&read_gta("gz2000.txt").unwrap());
+        let start_date = NaiveDate::parse_from_str("20110101", "%Y%m%d").unwrap();
+        let end_date = NaiveDate::parse_from_str("20240101", "%Y%m%d").unwrap();
+        let trans = Transaction::new(&[&hs300, &gz2000], Some(start_date), Some(end_date));
+
+        let mut it = trans.iter(true, true);
+        aip_monthly(&mut it, 1, &[1000.; 2], &[2.; 2]).unwrap();
+        let irr_aip = it.record().unwrap().irr_naive();
+
+        let mut it = trans.iter(true, true);
+        value_averaging(
+            &mut it,
+            Schedule::Monthly(1),
+            &[1000.; 2],
+            0.,
+            None,
+            true,
+            &[2.; 2],
+        )
+        .unwrap();
+        let irr_va = it.record().unwrap().irr_naive();
+        assert!(irr_va > irr_aip);
+        assert!(it.cash_log().unwrap().iter().all(|&x| x > -1e-6));
+
+        let mut it = trans.iter(true, true);
+        value_averaging(
+            &mut it,
+            Schedule::Monthly(1),
+            &[1000.; 2],
+            0.05,
+            Some(5000.),
+            false,
+            &[2.; 2],
+        )
+        .unwrap();
+        let record = it.record().unwrap();
+        assert!(record.records().iter().all(|rs| rs.investment() <= 5000.));
+        for j in 0..2 {
+            let record = it.fund_record(j).unwrap();
+            assert!(record.records().iter().all(|rs| rs.share() >= 0.));
+        }
+    }
+
+    #[test]
+    fn test_value_averaging_2() {
+        let hs300 = Fund::from(&read_gta("hs300.txt").unwrap());
+        let start_date = NaiveDate::parse_from_str("20230101", "%Y%m%d").unwrap();
+        let end_date = NaiveDate::parse_from_str("20240101", "%Y%m%d").unwrap();
+        let trans = Transaction::new(&[&hs300], Some(start_date), Some(end_date));
+        let mut it = trans.iter(false, true);
+        value_averaging(
+            &mut it,
+            Schedule::Weekly(Weekday::Mon),
+            &[100.],
+            0.,
+            None,
+            true,
+            &[0.],
+        )
+        .unwrap();
+        // Value after the k-th transaction is close to 100 * k. The
+        // first slice of record is the first day without transaction.
+        let record = it.fund_record(0).unwrap();
+        for (k, rs) in record.records().iter().enumerate().skip(1) {
+            let target = 100. * k as f64;
+            assert!((rs.present_value() / target - 1.).abs() < 0.05);
+        }
+    }
+}
@@ -0,0 +1,279 @@
+use crate::strategy::rebalance::rebalance_to_weights;
+use crate::utility::{annual_volatility, simple_returns};
+use crate::{Schedule, TransactionIterator};
+use ndarray::{s, Array1, ArrayView1};
+
+#[derive(Debug)]
+pub struct VolTargetError(&'static str);
+
+impl std::fmt::Display for VolTargetError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        writeln!(f, "VolTargetError: {}", self.0)
+    }
+}
+
+impl std::error::Error for VolTargetError {}
+
+/// Parameters of volatility targeting.
+#[derive(Debug, Clone)]
+pub struct VolTarget {
+    /// Target annual volatility, e.g. 0.1 for 10%.
+    pub target: f64,
+    /// Number of days of history to estimate the volatility.
+    pub window: usize,
+    /// Maximal sum of weights after scaling. Use 1 to forbid
+    /// borrowing.
+    pub max_leverage: f64,
+}
+
+/// Get the realized annual volatility of the invested part of the
+/// portfolio in the last `window` days.
+///
+/// Daily returns are calculated by the logged shares and the NAVs, so
+/// they are not affected by inflows and the cash held. Days without
+/// any investment are skipped, and `None` is returned if there are
+/// less than 2 returns or the log is not saved.
+fn realized_volatility(it: &TransactionIterator, window: usize) -> Option<f64> {
+    let navs = it.navs();
+    let n = navs.shape()[0];
+    if n <= window {
+        return None;
+    }
+    let shares: Vec<ArrayView1<f64>> = (0..it.nfunds())
+        .map(|j| it.share_log(j))
+        .collect::<Option<_>>()?;
+    let mut returns = Vec::new();
+    for t in n - window..n {
+        let mut invested = 0.;
+        let mut profit = 0.;
+        for (j, share) in shares.iter().enumerate() {
+            invested += share[t - 1] * navs[[t - 1, j]];
+            profit += share[t - 1] * (navs[[t, j]] - navs[[t - 1, j]]);
+        }
+        if invested > 0. {
+            returns.push(profit / invested);
+        }
+    }
+    if returns.len() < 2 {
+        return None;
+    }
+    // Skipped days are excluded from the span of the returns.
+    let dates = it.dates();
+    let days = (dates[n - 1] - dates[n - window - 1]).num_days() as f64 * returns.len() as f64
+        / window as f64;
+    Some(annual_volatility(Array1::from(returns).view(), days))
+}
+
+/// Get the ex-ante annual volatility of a portfolio of `weights`,
+/// normalized to be fully invested, by the NAVs of the last `window`
+/// days.
+fn expected_volatility(it: &TransactionIterator, weights: &[f64], window: usize) -> Option<f64> {
+    let navs = it.navs();
+    let n = navs.shape()[0];
+    let total: f64 = weights.iter().sum();
+    if n <= window || window < 2 || total <= 0. {
+        return None;
+    }
+    let returns = simple_returns(navs.slice(s![n - window - 1.., ..]));
+    let w = Array1::from_iter(weights.iter().map(|x| x / total));
+    let portfolio = returns.dot(&w);
+    let dates = it.dates();
+    let days = (dates[n - 1] - dates[n - window - 1]).num_days() as f64;
+    Some(annual_volatility(portfolio.view(), days))
+}
+
+/// Scale `weights` so that the volatility of the portfolio meets
+/// `config.target`.
+///
+/// The volatility of the invested part of the portfolio is estimated
+/// by its realized returns in history, which requires the log of the
+/// iterator. If the log is not saved or there are no investments in
+/// history, it is estimated by the NAVs as if `weights` were held.
+/// The scaled weights keep the proportion of `weights` and sum to
+/// `config.target / volatility`, capped by `config.max_leverage`.
+/// Weights are returned unchanged if the volatility is not available.
+pub fn vol_target_weights(
+    it: &TransactionIterator,
+    weights: &[f64],
+    config: &VolTarget,
+) -> Vec<f64> {
+    let total: f64 = weights.iter().sum();
+    let volatility = realized_volatility(it, config.window)
+        .or_else(|| expected_volatility(it, weights, config.window));
+    match volatility {
+        Some(v) if v > 0. && total > 0. => {
+            let gross = f64::min(config.target / v, config.max_leverage);
+            weights.iter().map(|w| w / total * gross).collect()
+        }
+        _ => weights.to_vec(),
+    }
+}
+
+/// Volatility targeting overlay for any strategy.
+///
+/// At each date of `schedule`, target weights of the funds are given
+/// by `strategy`, which are scaled by `vol_target_weights` and the
+/// funds are rebalanced to the scaled weights. `strategy` can be any
+/// allocation logic that decides by the iterator, such as
+/// `allocation_hint` or positions given by `kelly_hint`. The iterator
+/// should save log to estimate the realized volatility.
+///
+/// # Arguments
+///
+/// * `schedule` - Schedule of rebalancing.
+/// * `config` - Parameters of volatility targeting.
+/// * `fee_rates` - Fee as a fraction of the amount of each
+///   transaction for each fund.
+/// * `strategy` - Function giving the target weights of each fund.
+pub fn vol_target<F>(
+    it: &mut TransactionIterator,
+    schedule: Schedule,
+    config: &VolTarget,
+    fee_rates: &[f64],
+    mut strategy: F,
+) -> Result<(), Box<dyn std::error::Error>>
+where
+    F: FnMut(&TransactionIterator) -> Result<Vec<f64>, Box<dyn std::error::Error>>,
+{
+    if config.target <= 0. || config.max_leverage <= 0. || config.window < 2 {
+        return Err(Box::new(VolTargetError(
+            "target, max leverage should be positive and window should be at least 2",
+        )));
+    }
+    while it.next_schedule(schedule).is_some() {
+        let weights = strategy(it)?;
+        if weights.len() != it.nfunds() {
+            return Err(Box::new(VolTargetError(
+                "number of weights does not match number of funds",
+            )));
+        }
+        let scaled = vol_target_weights(it, &weights, config);
+        let comment = format!(
+            "volatility target, exposure {:.2}% -> {:.2}%",
+            100. * weights.iter().sum::<f64>(),
+            100. * scaled.iter().sum::<f64>()
+        );
+        rebalance_to_weights(it, &scaled, fee_rates, &comment)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::strategy::kelly::{kelly_general_hint, KellyConfig, Payoff};
+    use crate::*;
+
+    /// Realized volatility of total asset in the last `n` days.
+    fn asset_volatility(it: &TransactionIterator, n: usize) -> f64 {
+        let asset = it.asset_log().unwrap();
+        let asset = asset.slice(s![asset.len() - n - 1..]);
+        let returns = &asset.slice(s![1..]) / &asset.slice(s![..-1]) - 1.;
+        let dates = it.dates();
+        let days = (dates[dates.len() - 1] - dates[dates.len() - n - 1]).num_days() as f64;
+        annual_volatility(returns.view(), days)
+    }
+
+    #[test]
+    fn test_vol_target_1() {
+        let hs300 = Fund::from(&read_gta("hs300.txt").unwrap());
+        let gz2000 = Fund::from(&read_gta("gz2000.txt").unwrap());
+        let start_date = NaiveDate::parse_from_str("20150101", "%Y%m%d").unwrap();
+        let end_date = NaiveDate::parse_from_str("20240101", "%Y%m%d").unwrap();
+        let trans = Transaction::new(&[&hs300, &gz2000], Some(start_date), Some(end_date));
+        let config = VolTarget {
+            target: 0.1,
+            window: 60,
+            max_leverage: 1.,
+        };
+
+        let mut it = trans.iter(true, false);
+        it.inflow(10000.).unwrap();
+        vol_target(
+            &mut it,
+            Schedule::Weekly(Weekday::Mon),
+            &config,
+            &[0.; 2],
+            |_| Ok(vec![0.5, 0.5]),
+        )
+        .unwrap();
+        let scaled = asset_volatility(&it, 1500);
+        assert!(it.cash_log().unwrap().iter().all(|&x| x > -1e-9));
+
+        let mut it = trans.iter(true, false);
+        it.inflow(10000.).unwrap();
+        let config_full = VolTarget {
+            max_leverage: 1.,
+            target: f64::INFINITY,
+            ..config.clone()
+        };
+        vol_target(
+            &mut it,
+            Schedule::Weekly(Weekday::Mon),
+            &config_full,
+            &[0.; 2],
+            |_| Ok(vec![0.5, 0.5]),
+        )
+        .unwrap();
+        let full = asset_volatility(&it, 1500);
+        assert!(full > 0.15);
+        assert!((scaled - 0.1).abs() < 0.03);
+
+        // Kelly positions as the strategy.
+        let kelly = KellyConfig {
+            sampling: Schedule::Weekly(Weekday::Mon),
+            lookback: Duration::days(1000),
+            inflation: 0.015,
+            risk_bound: 0.01,
+            payoff: Payoff::Extreme,
+        };
+        let mut it = trans.iter(true, true);
+        it.goto(NaiveDate::parse_from_str("20180101", "%Y%m%d").unwrap());
+        it.inflow(10000.).unwrap();
+        vol_target(
+            &mut it,
+            Schedule::Weekly(Weekday::Mon),
+            &config,
+            &[0.; 2],
+            |it| {
+                (0..it.nfunds())
+                    .map(|j| Ok(kelly_general_hint(it, j, &kelly)?.position / it.nfunds() as f64))
+                    .collect()
+            },
+        )
+        .unwrap();
+        assert!(it.cash_log().unwrap().iter().all(|&x| x > -1e-9));
+    }
+
+    #[test]
+    fn test_vol_target_2() {
+        // A low volatility fund is leveraged.
+        let hs300 = Fund::from(&read_gta("hs300.txt").unwrap());
+        let mut bond = Fund::new("bond", "000000");
+        for (i, s) in hs300.data().iter().enumerate() {
+            let wiggle = if i % 2 == 0 { 1.001 } else { 0.999 };
+            bond.append(s.date(), 1.0001f64.powi(i as i32) * wiggle);
+        }
+        let start_date = NaiveDate::parse_from_str("20200101", "%Y%m%d").unwrap();
+        let trans = Transaction::new(&[&bond], Some(start_date), None);
+        let config = VolTarget {
+            target: 0.1,
+            window: 60,
+            max_leverage: 2.,
+        };
+        let mut it = trans.iter(true, false);
+        it.inflow(10000.).unwrap();
+        vol_target(&mut it, Schedule::Monthly(1), &config, &[0.], |_| {
+            Ok(vec![1.])
+        })
+        .unwrap();
+        let asset = it.asset_log().unwrap();
+        let fund_asset = it.fund_asset_log(0).unwrap();
+        let leverage = (0..it.ndays())
+            .filter(|&i| asset[i] > 0.)
+            .map(|i| fund_asset[i] / asset[i])
+            .fold(0., f64::max);
+        assert!(leverage > 1.9 && leverage < 2.1);
+        assert!(it.cash() < 0.);
+    }
+}
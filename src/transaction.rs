@@ -1,6 +1,6 @@
 use crate::{
-    common::warning, merge_records, utility::search_sorted, ConciseRecord, DataSlice,
-    DetailedRecord, Fund,
+    common::warning, record::merge, record::RecordLike, utility::search_sorted, ConciseRecord,
+    DataSlice, DetailedRecord, Fund,
 };
 use chrono::{Datelike, Duration, NaiveDate};
 use ndarray::{s, Array1, Array2, ArrayView1, ArrayView2, AssignElem, Axis, ShapeBuilder};
@@ -118,9 +118,30 @@ impl Transaction {
         &self.navs
     }
 
-    pub fn iter(&self, save_log: bool, save_record: bool) -> TransactionIterator {
+    pub fn iter(&self, save_log: bool, save_record: bool) -> TransactionIterator<'_> {
         TransactionIterator::new(self, save_log, save_record)
     }
+
+    /// Create a new Transaction of the dates from `start_date` to
+    /// `end_date`, which is excluded.
+    ///
+    /// Unspecified dates default to those of this transaction.
+    pub fn slice(&self, start_date: Option<NaiveDate>, end_date: Option<NaiveDate>) -> Transaction {
+        let start_date = start_date.unwrap_or(self.start_date);
+        let end_date = end_date.unwrap_or(self.end_date);
+        let beg = search_sorted(&self.date, &start_date, |d| *d, None);
+        let end = search_sorted(&self.date[beg..], &end_date, |d| *d, None) + beg;
+        let mut navs = Array2::zeros((end - beg, self.nfunds()).f());
+        navs.assign(&self.navs.slice(s![beg..end, ..]));
+        Transaction {
+            names: self.names.clone(),
+            codes: self.codes.clone(),
+            date: self.date[beg..end].to_vec(),
+            navs,
+            start_date,
+            end_date,
+        }
+    }
 }
 
 /// Records transaction operations during one iteration.
@@ -132,6 +153,7 @@ impl Transaction {
 /// This struct is necessary despite the existance of IterStatus, as
 /// we do not want the user to observe changes in the status of the
 /// TransactionIterator when they are making transactions.
+#[derive(Clone)]
 struct IterBuffer {
     cash: f64,
     shares: Vec<f64>,
@@ -152,6 +174,7 @@ impl IterBuffer {
 /// reaches end when `index` equals to `ndate`. `cash` is the current
 /// cash at the beginning of the day. `shares` is a list of floats
 /// indicating the shares of the funds at the beginning of the day.
+#[derive(Clone)]
 struct IterStatus {
     cash: f64,
     shares: Vec<f64>,
@@ -160,6 +183,7 @@ struct IterStatus {
 /// Log of history cash and shares.
 ///
 /// This is an optional struct for TransactionIterator.
+#[derive(Clone)]
 struct IterLog {
     cash: Array1<f64>,
     shares: Array2<f64>,
@@ -168,6 +192,7 @@ struct IterLog {
 /// Struct for storing records.
 ///
 /// This is an optional struct for TransactionIterator.
+#[derive(Clone)]
 struct IterRecord {
     // Investments of each fund is necessary for records.
     investments: Vec<f64>,
@@ -189,7 +214,64 @@ impl IterRecord {
 
 pub use chrono::Weekday;
 
+/// Schedule of periodic transactions.
+///
+/// `Weekly` steps to the given weekday and `Monthly` steps to the
+/// given day of month, see `TransactionIterator::next_weekday` and
+/// `TransactionIterator::next_month`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Schedule {
+    Daily,
+    Weekly(Weekday),
+    Monthly(u32),
+}
+
+impl Schedule {
+    /// Indices of `dates` that the schedule steps to.
+    ///
+    /// `dates` should be sorted. `Daily` selects all the dates and
+    /// `Weekly` selects the dates of the weekday. `Monthly` selects
+    /// the first date on or after the given day of the first month,
+    /// and then steps like `TransactionIterator::next_month`.
+    pub fn select(&self, dates: &[NaiveDate]) -> Vec<usize> {
+        match *self {
+            Schedule::Daily => (0..dates.len()).collect(),
+            Schedule::Weekly(weekday) => (0..dates.len())
+                .filter(|&i| dates[i].weekday() == weekday)
+                .collect(),
+            Schedule::Monthly(day) => {
+                let target = |year: i32, month: u32| {
+                    let (year, month) = if month > 12 {
+                        (year + 1, 1)
+                    } else {
+                        (year, month)
+                    };
+                    NaiveDate::from_ymd_opt(year, month, 1).unwrap()
+                        + Duration::days((day - 1) as i64)
+                };
+                let mut res = Vec::new();
+                let Some(first) = dates.first() else {
+                    return res;
+                };
+                let mut next = target(first.year(), first.month());
+                for (i, date) in dates.iter().enumerate() {
+                    if *date >= next {
+                        res.push(i);
+                        next = target(date.year(), date.month() + 1);
+                    }
+                }
+                res
+            }
+        }
+    }
+}
+
 /// Transact over a given `Transaction` object.
+///
+/// Cloning an iterator forks the simulation at the current date. The
+/// clone shares the `Transaction` object and can be stepped
+/// independently, so that different branches can be compared.
+#[derive(Clone)]
 pub struct TransactionIterator<'a> {
     transaction: &'a Transaction,
     index: usize,
@@ -199,6 +281,30 @@ pub struct TransactionIterator<'a> {
     iter_record: Option<IterRecord>,
 }
 
+/// Saved state of a `TransactionIterator`.
+///
+/// It holds the date, cash, shares, pending transactions, logs and
+/// records of the iterator, and is created by
+/// `TransactionIterator::snapshot`.
+#[derive(Clone)]
+pub struct Snapshot<'a> {
+    transaction: &'a Transaction,
+    index: usize,
+    iter_buffer: IterBuffer,
+    iter_status: IterStatus,
+    iter_log: Option<IterLog>,
+    iter_record: Option<IterRecord>,
+}
+
+impl Snapshot<'_> {
+    /// Date of the iterator when the snapshot is taken, or `None` if
+    /// the transaction has no day.
+    pub fn today(&self) -> Option<NaiveDate> {
+        let date = &self.transaction.date;
+        date.get(self.index).or(date.last()).copied()
+    }
+}
+
 impl<'a> TransactionIterator<'a> {
     pub(crate) fn new(trans: &'a Transaction, save_log: bool, save_record: bool) -> Self {
         let ndays = trans.ndays();
@@ -256,6 +362,11 @@ impl<'a> TransactionIterator<'a> {
         }
     }
 
+    /// The `Transaction` object being iterated over.
+    pub fn transaction(&self) -> &'a Transaction {
+        self.transaction
+    }
+
     pub fn nfunds(&self) -> usize {
         self.transaction.nfunds()
     }
@@ -282,6 +393,19 @@ impl<'a> TransactionIterator<'a> {
         self.iter_status.shares[idx]
     }
 
+    /// NAV of specified fund id at which transactions of today are
+    /// settled.
+    ///
+    /// This is the NAV at the *end* of the day, which is not known
+    /// when making decisions in real life. It is provided for
+    /// bookkeeping such as evaluating the shares or income of a
+    /// transaction, and strategies should make decisions by `navs`
+    /// instead.
+    pub fn nav(&self, idx: usize) -> Result<f64, TransactionError> {
+        self.assert_not_finished()?;
+        Ok(self.transaction.navs[[self.index, idx]])
+    }
+
     /// Asset of specfied fund id at the *beginning* of the day.
     pub fn fund_asset(&self, idx: usize) -> f64 {
         if self.index == 0 {
@@ -306,23 +430,55 @@ impl<'a> TransactionIterator<'a> {
         }
     }
 
+    /// Save the current state of the iterator.
+    ///
+    /// Transactions made today but not settled yet are saved as well.
+    pub fn snapshot(&self) -> Snapshot<'a> {
+        Snapshot {
+            transaction: self.transaction,
+            index: self.index,
+            iter_buffer: self.iter_buffer.clone(),
+            iter_status: self.iter_status.clone(),
+            iter_log: self.iter_log.clone(),
+            iter_record: self.iter_record.clone(),
+        }
+    }
+
+    /// Rewind the iterator to a snapshot taken from an iterator over
+    /// the same `Transaction` object.
+    ///
+    /// Whether log and record are saved also follows the snapshot.
+    pub fn restore(&mut self, snapshot: &Snapshot<'a>) -> Result<&mut Self, TransactionError> {
+        if !std::ptr::eq(self.transaction, snapshot.transaction) {
+            return Err(TransactionError(
+                "snapshot is taken from another transaction",
+            ));
+        }
+        self.index = snapshot.index;
+        self.iter_buffer.clone_from(&snapshot.iter_buffer);
+        self.iter_status.clone_from(&snapshot.iter_status);
+        self.iter_log.clone_from(&snapshot.iter_log);
+        self.iter_record.clone_from(&snapshot.iter_record);
+        Ok(self)
+    }
+
     /// Sequence of dates have iterated.
     pub fn dates(&self) -> &[NaiveDate] {
         &self.transaction.date[..self.index]
     }
 
     /// A 2-d array of NAVs in history.
-    pub fn navs(&self) -> ArrayView2<f64> {
+    pub fn navs(&self) -> ArrayView2<'_, f64> {
         self.transaction.navs.slice(s![..self.index, ..])
     }
 
     /// Log of cash.
-    pub fn cash_log(&self) -> Option<ArrayView1<f64>> {
+    pub fn cash_log(&self) -> Option<ArrayView1<'_, f64>> {
         Some(self.iter_log.as_ref()?.cash.slice(s![..self.index]))
     }
 
     /// Log of shares
-    pub fn share_log(&self, idx: usize) -> Option<ArrayView1<f64>> {
+    pub fn share_log(&self, idx: usize) -> Option<ArrayView1<'_, f64>> {
         Some(self.iter_log.as_ref()?.shares.slice(s![..self.index, idx]))
     }
 
@@ -357,8 +513,8 @@ impl<'a> TransactionIterator<'a> {
         if let Some(ref mut record) = self.iter_record {
             if !record.cash_comment_buffer.is_empty() {
                 record.cash_comment_buffer.push_str("; ");
-                record.cash_comment_buffer.push_str(comment);
             }
+            record.cash_comment_buffer.push_str(comment);
         }
         Ok(self)
     }
@@ -391,8 +547,8 @@ impl<'a> TransactionIterator<'a> {
         if let Some(ref mut record) = self.iter_record {
             if !record.fund_comment_buffer[fundid].is_empty() {
                 record.fund_comment_buffer[fundid].push_str("; ");
-                record.fund_comment_buffer[fundid].push_str(comment);
             }
+            record.fund_comment_buffer[fundid].push_str(comment);
         }
         Ok(self)
     }
@@ -424,8 +580,8 @@ impl<'a> TransactionIterator<'a> {
         if let Some(ref mut record) = self.iter_record {
             if !record.fund_comment_buffer[fundid].is_empty() {
                 record.fund_comment_buffer[fundid].push_str("; ");
-                record.fund_comment_buffer[fundid].push_str(comment);
             }
+            record.fund_comment_buffer[fundid].push_str(comment);
         }
         Ok(self)
     }
@@ -521,7 +677,7 @@ impl<'a> TransactionIterator<'a> {
     }
 
     /// Step to next `weekday`, return if the iteration reaches the end.
-
+    ///
     /// # Arguments
     ///
     /// * `weekday`/// If not given, it will de derived from `today`.
@@ -593,6 +749,15 @@ impl<'a> TransactionIterator<'a> {
         Some(self)
     }
 
+    /// Step to the next date of `schedule`.
+    pub fn next_schedule(&mut self, schedule: Schedule) -> Option<&mut Self> {
+        match schedule {
+            Schedule::Daily => self.next_day(),
+            Schedule::Weekly(weekday) => self.next_weekday(Some(weekday)),
+            Schedule::Monthly(day) => self.next_month(Some(day)),
+        }
+    }
+
     pub fn cash_record(&self) -> Option<&ConciseRecord> {
         if let Some(ref record) = self.iter_record {
             Some(&record.cash_record)
@@ -611,11 +776,10 @@ impl<'a> TransactionIterator<'a> {
 
     pub fn record(&self) -> Option<ConciseRecord> {
         if let Some(ref record) = self.iter_record {
-            let res = ConciseRecord::new("Combined Record", "");
-            let mut res = merge_records!(&res, &record.cash_record);
-            for rec in record.fund_records.iter() {
-                res = merge_records!(&res, rec)
-            }
+            let mut records: Vec<&dyn RecordLike> = vec![&record.cash_record];
+            records.extend(record.fund_records.iter().map(|r| r as &dyn RecordLike));
+            let mut res = merge(&records, false);
+            res.name = "Combined Record".to_string();
             Some(res)
         } else {
             None
@@ -624,6 +788,7 @@ impl<'a> TransactionIterator<'a> {
 }
 
 #[cfg(test)]
+#[allow(clippy::redundant_pattern_matching)]
 mod test {
     use super::*;
     use chrono::NaiveDate;
@@ -641,6 +806,21 @@ mod test {
         assert!((t.navs()[[1, 1]] - 6299.53).abs() < 1e-3);
     }
 
+    #[test]
+    fn test_transaction_slice() {
+        use crate::read_gta;
+        let hs300 = Fund::from(&read_gta("hs300.txt").unwrap());
+        let gz2000 = Fund::from(&read_gta("gz2000.txt").unwrap());
+        let t = Transaction::new(&[&hs300, &gz2000], None, None);
+        let start_date = NaiveDate::parse_from_str("2020-01-01", "%Y-%m-%d").unwrap();
+        let end_date = NaiveDate::parse_from_str("2021-01-01", "%Y-%m-%d").unwrap();
+        let s = t.slice(Some(start_date), Some(end_date));
+        let expected = Transaction::new(&[&hs300, &gz2000], Some(start_date), Some(end_date));
+        assert_eq!(s.date(), expected.date());
+        assert_eq!(s.navs(), expected.navs());
+        assert_eq!(s.end_date(), end_date);
+    }
+
     /// Test iter `next_day`.
     #[test]
     fn test_transaction1() {
@@ -741,6 +921,73 @@ mod test {
             .is_none());
     }
 
+    #[test]
+    fn test_snapshot() {
+        use crate::read_gta;
+        let hs300 = Fund::from(&read_gta("hs300.txt").unwrap());
+        let gz2000 = Fund::from(&read_gta("gz2000.txt").unwrap());
+        let start_date = NaiveDate::parse_from_str("2023-01-01", "%Y-%m-%d").unwrap();
+        let t = Transaction::new(&[&hs300, &gz2000], Some(start_date), None);
+        let mut it = t.iter(true, true);
+        it.inflow_comment(100., "initial").unwrap();
+        it.goto(NaiveDate::parse_from_str("2023-06-01", "%Y-%m-%d").unwrap());
+        it.buy(0, 50., 0.).unwrap();
+        let snapshot = it.snapshot();
+
+        // Buy now or wait a month in two branches.
+        let mut wait = it.clone();
+        wait.next_month(None);
+        wait.buy(1, 30., 0.).unwrap();
+        it.buy(1, 30., 0.).unwrap();
+        while it.next_day().is_some() {}
+        while wait.next_day().is_some() {}
+        assert!(it.share(1) != wait.share(1));
+        assert_eq!(it.cash(), wait.cash());
+
+        let asset = it.asset();
+        let record_len = it.record().unwrap().len();
+        it.restore(&snapshot).unwrap();
+        assert_eq!(Some(it.today()), snapshot.today());
+        assert_eq!(it.share(0), 0.);
+        it.buy(1, 30., 0.).unwrap();
+        while it.next_day().is_some() {}
+        assert_eq!(it.asset(), asset);
+        assert_eq!(it.record().unwrap().len(), record_len);
+        assert_eq!(it.cash_log().unwrap().len(), t.ndays());
+
+        let other = t.slice(None, None);
+        assert!(other.iter(false, false).restore(&snapshot).is_err());
+
+        // A transaction can be empty.
+        let empty = Transaction::new(&[&hs300], Some(start_date), Some(start_date));
+        assert_eq!(empty.ndays(), 0);
+        assert_eq!(empty.iter(false, false).snapshot().today(), None);
+    }
+
+    #[test]
+    fn test_schedule_select() {
+        use crate::read_gta;
+        let hs300 = Fund::from(&read_gta("hs300.txt").unwrap());
+        let start_date = NaiveDate::parse_from_str("2020-01-01", "%Y-%m-%d").unwrap();
+        let t = Transaction::new(&[&hs300], Some(start_date), None);
+        for schedule in [
+            Schedule::Weekly(Weekday::Tue),
+            Schedule::Monthly(1),
+            Schedule::Monthly(31),
+        ] {
+            // Iterate from the first selected date.
+            let selected = schedule.select(t.date());
+            let mut it = t.iter(false, false);
+            it.goto(t.date()[selected[0]]);
+            let mut expected = Vec::new();
+            while it.next_schedule(schedule).is_some() {
+                expected.push(it.dates().len());
+            }
+            assert_eq!(selected[1..], expected);
+        }
+        assert_eq!(Schedule::Daily.select(t.date()).len(), t.ndays());
+    }
+
     /// Test iter `next_month`.
     #[test]
     #[should_panic]
@@ -758,4 +1005,25 @@ mod test {
         }
         it.sell(1, it.share(1), 0.2).unwrap();
     }
+
+    /// Comments are recorded from the first call, and the following
+    /// ones on the same day are joined.
+    #[test]
+    fn test_comment() {
+        use crate::{read_gta, record::RecordSlice};
+        let hs300 = Fund::from(&read_gta("hs300.txt").unwrap());
+        let start_date = NaiveDate::parse_from_str("2024-01-01", "%Y-%m-%d").unwrap();
+        let t = Transaction::new(&[&hs300], Some(start_date), None);
+        let mut it = t.iter(false, true);
+        it.inflow_comment(100., "deposit").unwrap();
+        it.buy_comment(0, 50., 0., "buy").unwrap();
+        it.buy_comment(0, 10., 0., "buy more").unwrap();
+        it.next_day();
+        it.sell_comment(0, it.share(0), 0., "sell").unwrap();
+        it.next_day();
+        assert_eq!(it.cash_record().unwrap()[0].comment(), "deposit");
+        let record = it.fund_record(0).unwrap();
+        assert_eq!(record[0].comment(), "buy; buy more");
+        assert_eq!(record[1].comment(), "sell");
+    }
 }
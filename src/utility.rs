@@ -1,3 +1,4 @@
+use ndarray::{s, Array1, Array2, ArrayView1, ArrayView2, Axis};
 use std::cmp::Ordering;
 pub const DAYS_PER_YEAR: f64 = 360.;
 
@@ -164,6 +165,167 @@ pub(crate) fn newton1d(
     None
 }
 
+/// Look up a value in a step table.
+///
+/// `table` is a list of `(bound, value)` sorted by `bound`. The value
+/// of the first item whose bound is larger than `x` is returned. If
+/// `x` is not less than all the bounds, zero is returned.
+pub(crate) fn step_lookup(table: &[(f64, f64)], x: f64) -> f64 {
+    table
+        .iter()
+        .find(|(bound, _)| x < *bound)
+        .map_or(0., |(_, value)| *value)
+}
+
+/// Piecewise linear interpolation.
+///
+/// `points` is a list of `(x, y)` sorted by `x`. Values outside the
+/// range of `points` are clamped to the values at the ends.
+pub(crate) fn interp(points: &[(f64, f64)], x: f64) -> f64 {
+    let idx = points.partition_point(|p| p.0 < x);
+    if idx == 0 {
+        points[0].1
+    } else if idx == points.len() {
+        points[idx - 1].1
+    } else {
+        let (x0, y0) = points[idx - 1];
+        let (x1, y1) = points[idx];
+        y0 + (y1 - y0) * (x - x0) / (x1 - x0)
+    }
+}
+
+/// Calculate drawdown of a series of values.
+///
+/// The drawdown at each position is the fraction lost from the
+/// maximal value before it, thus it ranges from 0 to 1 for positive
+/// values.
+pub(crate) fn drawdown(values: &[f64]) -> Vec<f64> {
+    let mut peak = f64::NEG_INFINITY;
+    values
+        .iter()
+        .map(|&v| {
+            peak = f64::max(peak, v);
+            if peak > 0. {
+                1. - v / peak
+            } else {
+                0.
+            }
+        })
+        .collect()
+}
+
+/// Calculate the `q`-th quantile of values by linear interpolation.
+///
+/// `q` ranges from 0 to 1, where 0 gives the minimum and 1 gives the
+/// maximum. `values` should not be empty.
+pub(crate) fn quantile(values: &[f64], q: f64) -> f64 {
+    let mut sorted = values.to_vec();
+    sorted.sort_by(|a, b| a.partial_cmp(b).unwrap_or(Ordering::Equal));
+    let pos = q.clamp(0., 1.) * (sorted.len() - 1) as f64;
+    let lo = pos.floor() as usize;
+    let hi = pos.ceil() as usize;
+    sorted[lo] + (sorted[hi] - sorted[lo]) * (pos - lo as f64)
+}
+
+/// Calculate simple returns of each column of `navs`.
+pub(crate) fn simple_returns(navs: ArrayView2<f64>) -> Array2<f64> {
+    &navs.slice(s![1.., ..]) / &navs.slice(s![..-1, ..]) - 1.
+}
+
+/// Calculate the mean and the sample covariance of `samples`, whose
+/// rows are observations and columns are variables.
+pub(crate) fn covariance(samples: ArrayView2<f64>) -> (Array1<f64>, Array2<f64>) {
+    let mean = samples.mean_axis(Axis(0)).unwrap();
+    let centered = &samples - &mean;
+    let cov = centered.t().dot(&centered) / (samples.shape()[0] - 1) as f64;
+    (mean, cov)
+}
+
+/// Annualize the volatility of `returns`, which span `days` calendar
+/// days.
+///
+/// The number of periods per year is estimated by the number of
+/// returns and `days`, so it works for returns of any frequency.
+pub(crate) fn annual_volatility(returns: ArrayView1<f64>, days: f64) -> f64 {
+    let periods = returns.len() as f64 / (days / DAYS_PER_YEAR);
+    returns.std(1.) * periods.sqrt()
+}
+
+/// Project a vector onto the set `{x | x >= 0, sum(x) <= 1}`.
+///
+/// Negative items are clipped to zero. If the sum still exceeds 1,
+/// the vector is projected onto the probability simplex.
+pub(crate) fn project_capped_simplex(v: &[f64]) -> Vec<f64> {
+    let clipped: Vec<f64> = v.iter().map(|&x| f64::max(x, 0.)).collect();
+    if clipped.iter().sum::<f64>() <= 1. {
+        clipped
+    } else {
+        project_simplex(v)
+    }
+}
+
+/// Project a vector onto the probability simplex
+/// `{x | x >= 0, sum(x) = 1}`.
+///
+/// All the items are shifted by the same amount and the negative
+/// items are clipped to zero.
+pub(crate) fn project_simplex(v: &[f64]) -> Vec<f64> {
+    let mut u = v.to_vec();
+    u.sort_by(|a, b| b.partial_cmp(a).unwrap_or(Ordering::Equal));
+    let mut cumsum = 0.;
+    let mut theta = 0.;
+    for (j, &uj) in u.iter().enumerate() {
+        cumsum += uj;
+        let t = (cumsum - 1.) / (j + 1) as f64;
+        if uj - t > 0. {
+            theta = t;
+        }
+    }
+    v.iter().map(|&x| f64::max(x - theta, 0.)).collect()
+}
+
+/// Maximize `mu' x - x' cov x / 2` subject to `x >= 0, sum(x) <= 1`.
+///
+/// The problem is solved by projected gradient ascent, whose step
+/// size is the inverse of an upper bound of the largest eigenvalue of
+/// `cov`. `cov` should be symmetric and positive semi-definite.
+///
+/// # Arguments
+///
+/// * `fully_invested` - Use `sum(x) = 1` instead of `sum(x) <= 1`.
+/// * `tol` - The iteration stops when no item changes more than `tol`.
+/// * `maxiter` - The maximum number of iterations.
+pub(crate) fn maximize_quadratic(
+    mu: ArrayView1<f64>,
+    cov: ArrayView2<f64>,
+    fully_invested: bool,
+    tol: f64,
+    maxiter: usize,
+) -> Array1<f64> {
+    let lipschitz = cov
+        .rows()
+        .into_iter()
+        .map(|row| row.iter().map(|x| x.abs()).sum::<f64>())
+        .fold(0., f64::max);
+    let step = 1. / f64::max(lipschitz, 1e-12);
+    let project = if fully_invested {
+        project_simplex
+    } else {
+        project_capped_simplex
+    };
+    let mut x = Array1::<f64>::zeros(mu.len());
+    for _ in 0..maxiter {
+        let gradient = &mu - &cov.dot(&x);
+        let new_x = Array1::from(project((&x + &(gradient * step)).as_slice().unwrap()));
+        let change = (&new_x - &x).iter().fold(0., |m: f64, d| m.max(d.abs()));
+        x = new_x;
+        if change < tol {
+            break;
+        }
+    }
+    x
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
@@ -201,6 +363,91 @@ mod test {
         assert!((x0 + 1.).abs() < 1e-3);
     }
 
+    #[test]
+    fn test_step_lookup() {
+        let table = [(20., 2.), (50., 1.5), (80., 1.)];
+        assert_eq!(step_lookup(&table, 10.), 2.);
+        assert_eq!(step_lookup(&table, 20.), 1.5);
+        assert_eq!(step_lookup(&table, 79.9), 1.);
+        assert_eq!(step_lookup(&table, 80.), 0.);
+    }
+
+    #[test]
+    fn test_interp() {
+        let points = [(-0.2, 2.), (0., 1.), (0.2, 0.5)];
+        assert_eq!(interp(&points, -0.5), 2.);
+        assert_eq!(interp(&points, -0.1), 1.5);
+        assert_eq!(interp(&points, 0.), 1.);
+        assert_eq!(interp(&points, 0.1), 0.75);
+        assert_eq!(interp(&points, 0.5), 0.5);
+    }
+
+    #[test]
+    fn test_quantile() {
+        let values = [3., 1., 4., 2., 5.];
+        assert_eq!(quantile(&values, 0.), 1.);
+        assert_eq!(quantile(&values, 1.), 5.);
+        assert_eq!(quantile(&values, 0.5), 3.);
+        assert_eq!(quantile(&values, 0.125), 1.5);
+    }
+
+    #[test]
+    fn test_project_capped_simplex() {
+        assert_eq!(
+            project_capped_simplex(&[0.2, -0.1, 0.3]),
+            vec![0.2, 0., 0.3]
+        );
+        let x = project_capped_simplex(&[0.8, 0.6, -1.]);
+        assert!((x[0] - 0.6).abs() < 1e-12);
+        assert!((x[1] - 0.4).abs() < 1e-12);
+        assert_eq!(x[2], 0.);
+    }
+
+    #[test]
+    fn test_covariance() {
+        use ndarray::array;
+        let navs = array![[1., 2.], [1.1, 1.8], [1.21, 1.98]];
+        let (mean, cov) = covariance(simple_returns(navs.view()).view());
+        assert!((mean[0] - 0.1).abs() < 1e-12);
+        assert!(mean[1].abs() < 1e-12);
+        assert!(cov[[0, 0]].abs() < 1e-12);
+        assert!((cov[[1, 1]] - 0.02).abs() < 1e-12);
+    }
+
+    #[test]
+    fn test_annual_volatility() {
+        use ndarray::array;
+        let returns = array![0.01, -0.01, 0.01, -0.01];
+        let std = returns.std(1.);
+        let vol = annual_volatility(returns.view(), DAYS_PER_YEAR / 90.);
+        assert!((vol - std * 360f64.sqrt()).abs() < 1e-12);
+    }
+
+    #[test]
+    fn test_maximize_quadratic() {
+        use ndarray::array;
+        // Unconstrained optimum inv(cov) * mu = [0.2, 0.3] is feasible.
+        let cov = array![[1., 0.], [0., 2.]];
+        let x = maximize_quadratic(array![0.2, 0.6].view(), cov.view(), false, 1e-12, 10000);
+        assert!((x[0] - 0.2).abs() < 1e-6);
+        assert!((x[1] - 0.3).abs() < 1e-6);
+        // Minimal variance of a fully invested portfolio.
+        let x = maximize_quadratic(array![0., 0.].view(), cov.view(), true, 1e-12, 10000);
+        assert!((x[0] - 2. / 3.).abs() < 1e-6);
+        assert!((x[1] - 1. / 3.).abs() < 1e-6);
+        // Constrained by the sum and the lower bound.
+        let x = maximize_quadratic(
+            array![2., 2., -1.].view(),
+            Array2::eye(3).view(),
+            false,
+            1e-12,
+            10000,
+        );
+        assert!((x[0] - 0.5).abs() < 1e-6);
+        assert!((x[1] - 0.5).abs() < 1e-6);
+        assert_eq!(x[2], 0.);
+    }
+
     #[test]
     fn test_irr() {
         let days_array = [720., 360., 0.];